@@ -1,8 +1,22 @@
+mod add;
 mod common;
+mod fetch;
 mod list;
+mod lock;
 mod pull;
+mod remove;
+mod status;
+mod summary;
 mod sync;
+mod unshallow;
 
+pub use self::add::add;
+pub use self::common::Cancelled;
+pub use self::fetch::fetch;
 pub use self::list::{list, list_unknown};
+pub use self::lock::lock;
 pub use self::pull::pull;
+pub use self::remove::remove;
+pub use self::status::status;
 pub use self::sync::sync;
+pub use self::unshallow::unshallow;
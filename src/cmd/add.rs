@@ -0,0 +1,24 @@
+use anyhow::{Error, anyhow};
+
+use super::sync::sync;
+use crate::config::{self, Config, Filter, Log};
+
+pub async fn add(
+    cfg: &Config,
+    name: &str,
+    spec: &str,
+    type_: Option<&str>,
+    branch: Option<&str>,
+    optional: bool,
+) -> Result<(), Error> {
+    let path = cfg
+        .file()
+        .ok_or_else(|| anyhow!("Config file is not available"))?;
+    config::add_repo(path, name, spec, type_, branch, optional)?;
+    let cfg = config::load_config(path)?;
+    let filter = Filter {
+        names: Some(vec![name]),
+        ..Filter::default()
+    };
+    sync(&cfg, &filter, false, Log::Off, false).await
+}
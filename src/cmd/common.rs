@@ -1,39 +1,247 @@
+use std::fmt;
 use std::future::Future;
+use std::path::Path;
 use std::sync::Arc;
 
 use anyhow::Error;
 use futures::future;
-use tokio::sync::Semaphore;
+use tokio::signal;
+use tokio::sync::{Semaphore, watch};
 use tokio::task::JoinHandle;
 
-use crate::git::GitError;
+use super::summary::Report;
+use crate::config::{Config, Remote, Selection};
+use crate::git::{Finished, GitError, RepoInfo, TimeoutError};
 use crate::print;
+use crate::progress;
 
-pub async fn bounded_run<R>(run: R, semaphore: Arc<Semaphore>) -> R::Output
+/// Returns the repo fields of JSON records for the repo directory.
+pub fn repo_info(cfg: &Config, name: &str) -> RepoInfo {
+    let (url, selection) = match cfg.selection(name) {
+        Some(Selection::Explicit(repo)) => (Some(repo.url()), "explicit"),
+        Some(Selection::Optional(repo)) => (Some(repo.url()), "optional"),
+        None => (None, "unknown"),
+    };
+    RepoInfo {
+        url,
+        selection,
+        exists: Path::new(name).is_dir(),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Cancelled {
+    /// Repo whose autostashed changes were not reapplied before cancellation.
+    pub stash_left: Option<String>,
+}
+
+impl std::error::Error for Cancelled {}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+/// Limits the number of concurrent tasks and cancels them on Ctrl-C.
+pub struct Limiter {
+    semaphore: Semaphore,
+    interrupted: watch::Receiver<bool>,
+}
+
+impl Limiter {
+    pub fn new(concurrency: usize) -> Arc<Self> {
+        let (tx, rx) = watch::channel(false);
+        tokio::spawn(async move {
+            if signal::ctrl_c().await.is_err() {
+                return;
+            }
+            print::info(print::warn("Interrupted, cancelling..."));
+            let _ = tx.send(true);
+            // Exiting here would skip killing git processes and removing
+            // partial clones, so let the cancellation finish.
+            while signal::ctrl_c().await.is_ok() {
+                print::info(print::warn("Still cancelling, please wait..."));
+            }
+        });
+        Arc::new(Limiter {
+            semaphore: Semaphore::new(concurrency),
+            interrupted: rx,
+        })
+    }
+}
+
+/// Runs the future when a permit is available. Dropping the future on
+/// interruption kills running git processes.
+pub async fn bounded_run<R, T>(run: R, limiter: Arc<Limiter>) -> Result<T, Error>
 where
-    R: Future,
+    R: Future<Output = Result<T, Error>>,
 {
-    let permit = semaphore.acquire().await;
-    let result = run.await;
-    drop(permit);
-    result
+    let mut interrupted = limiter.interrupted.clone();
+    tokio::select! {
+        biased;
+        Ok(_) = interrupted.wait_for(|i| *i) => Err(Cancelled::default().into()),
+        result = async {
+            let _permit = limiter.semaphore.acquire().await;
+            run.await
+        } => result,
+    }
+}
+
+/// Result of a task which may have retried git commands.
+pub trait Retried {
+    /// Returns the key and the retry count if any command was retried.
+    fn retried(&self) -> Option<(&str, u32)>;
+}
+
+impl Retried for Finished {
+    fn retried(&self) -> Option<(&str, u32)> {
+        (self.retries > 0).then_some((&self.key, self.retries))
+    }
 }
 
-pub async fn join_handles(
+impl Retried for Report {
+    fn retried(&self) -> Option<(&str, u32)> {
+        (self.retries > 0).then_some((&self.key, self.retries))
+    }
+}
+
+impl<T: Retried> Retried for Option<T> {
+    fn retried(&self) -> Option<(&str, u32)> {
+        self.as_ref().and_then(T::retried)
+    }
+}
+
+impl<T: Retried, U> Retried for (T, U) {
+    fn retried(&self) -> Option<(&str, u32)> {
+        self.0.retried()
+    }
+}
+
+/// Results of successful tasks and the number of cancelled ones.
+pub struct Joined<T> {
+    pub succeeded: Vec<T>,
+    pub cancelled: usize,
+    pub stash_left: Vec<String>,
+}
+
+impl<T> Joined<T> {
+    /// Returns an error if any task was cancelled, so that the command exits
+    /// with failure after reporting its results.
+    pub fn check_cancelled(&self) -> Result<(), Error> {
+        if self.cancelled > 0 {
+            Err(Cancelled::default().into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub async fn join_handles<T>(
     name: &str,
-    handles: Vec<JoinHandle<Result<String, Error>>>,
-) -> Result<(), Error> {
-    let results = future::join_all(handles).await;
+    handles: Vec<(String, JoinHandle<Result<T, Error>>)>,
+) -> Result<Joined<T>, Error>
+where
+    T: Retried,
+{
+    let overall = progress::overall(handles.len(), name);
+    let results = future::join_all(handles.into_iter().map(|(key, handle)| async {
+        let result = handle.await;
+        if let Some(bar) = &overall {
+            bar.inc(1);
+        }
+        (key, result)
+    }))
+    .await;
+    if let Some(bar) = overall {
+        bar.finish_and_clear();
+    }
+    let total = results.len();
     let mut has_error = false;
-    for result in results {
-        if let Err(err) = result? {
-            let git_err = err.downcast::<GitError>()?;
-            if !has_error {
-                println!("\nThe following {} got error!", name);
-                has_error = true;
+    let mut succeeded = vec![];
+    let mut timed_out = vec![];
+    let mut cancelled = 0;
+    let mut stash_left = vec![];
+    let mut retried = vec![];
+    for (key, result) in results {
+        match result.unwrap_or_else(|err| Err(err.into())) {
+            Ok(value) => {
+                if let Some((key, count)) = value.retried() {
+                    retried.push((key.to_string(), count));
+                }
+                succeeded.push(value);
+            }
+            Err(err) => {
+                let err = match err.downcast::<Cancelled>() {
+                    Ok(cancelled_err) => {
+                        cancelled += 1;
+                        stash_left.extend(cancelled_err.stash_left);
+                        continue;
+                    }
+                    Err(err) => err,
+                };
+                let git_err = match err.downcast::<TimeoutError>() {
+                    Ok(timeout_err) => {
+                        if timeout_err.retries > 0 {
+                            retried.push((timeout_err.key.clone(), timeout_err.retries));
+                        }
+                        timed_out.push(timeout_err);
+                        continue;
+                    }
+                    Err(err) => err,
+                };
+                // Errors other than git failures, such as I/O errors, are
+                // reported under the repo of the task.
+                let git_err = git_err
+                    .downcast::<GitError>()
+                    .unwrap_or_else(|err| GitError {
+                        key,
+                        msg: err.to_string(),
+                        retries: 0,
+                    });
+                if git_err.retries > 0 {
+                    retried.push((git_err.key.clone(), git_err.retries));
+                }
+                if !has_error {
+                    print::info(format_args!("\nThe following {} got error!", name));
+                    has_error = true;
+                }
+                print::info(format_args!(
+                    "{}: {}",
+                    print::warn(&git_err.key),
+                    git_err.msg
+                ));
             }
-            println!("{}: {}", print::warn(&git_err.key), git_err.msg);
         }
     }
-    Ok(())
+    if !timed_out.is_empty() {
+        print::info(format_args!("\nThe following {} timed out!", name));
+        for err in timed_out {
+            print::info(format_args!(
+                "{}: killed after {}s",
+                print::warn(&err.key),
+                err.timeout.as_secs()
+            ));
+        }
+    }
+    if !retried.is_empty() {
+        print::info(format_args!("\nThe following {} were retried.", name));
+        for (key, count) in retried {
+            print::info(format_args!("{}: {} retries", key, count));
+        }
+    }
+    if cancelled > 0 {
+        print::info(format_args!(
+            "\nInterrupted, {} of {} {} completed, {} cancelled.",
+            total - cancelled,
+            total,
+            name,
+            cancelled
+        ));
+    }
+    Ok(Joined {
+        succeeded,
+        cancelled,
+        stash_left,
+    })
 }
@@ -0,0 +1,68 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Error;
+
+use super::common::{Limiter, bounded_run, join_handles, repo_info};
+use crate::config::{Config, Filter, RepoOptions};
+use crate::git::{Finished, Git, RepoInfo};
+use crate::print;
+
+async fn fetch_one<'a, G>(
+    git: &'a G,
+    dir: &Path,
+    opts: &RepoOptions,
+    info: RepoInfo,
+) -> Result<(Finished, bool), Error>
+where
+    G: Git<'a>,
+{
+    let before = git.remote_refs(dir).await?;
+    let finished = git.fetch(dir, opts, info).await?;
+    let after = git.remote_refs(dir).await?;
+    Ok((finished, before != after))
+}
+
+pub async fn fetch(cfg: &Config, filter: &Filter<'_>) -> Result<(), Error> {
+    let limiter = Limiter::new(cfg.concurrency());
+    let mut handles = vec![];
+    for result in cfg.repos(filter) {
+        match result {
+            Ok((dir, _)) => {
+                if !Path::new(dir).is_dir() {
+                    continue;
+                }
+                let limiter = Arc::clone(&limiter);
+                let path = PathBuf::from(&dir);
+                let git = cfg.git().clone();
+                let opts = cfg.options(dir).clone();
+                let info = repo_info(cfg, dir);
+                handles.push((
+                    dir.to_string(),
+                    tokio::spawn(async move {
+                        bounded_run(fetch_one(&git, &path, &opts, info), limiter).await
+                    }),
+                ));
+            }
+            Err(err) => {
+                print::info(err);
+            }
+        }
+    }
+    let fetched = join_handles("fetch", handles).await?;
+    let mut has_update = false;
+    for (finished, updated) in &fetched.succeeded {
+        if !*updated {
+            continue;
+        }
+        if !has_update {
+            print::info("\nThe following repos received new commits.");
+            has_update = true;
+        }
+        print::info(print::good(&finished.key));
+    }
+    if !has_update {
+        print::info("\nNo new commits.");
+    }
+    fetched.check_cancelled()
+}
@@ -3,29 +3,40 @@ use std::path::Path;
 
 use anyhow::{anyhow, Error};
 
-use crate::config::{Config, Remote, Selection};
-use crate::print;
+use serde_json::json;
 
-pub fn list(cfg: &Config, default: bool, optional: bool) -> Result<(), Error> {
-    for (dir, select) in cfg.repos(None).flatten() {
+use crate::config::{Config, Filter, Remote, Selection};
+use crate::print::{self, Format};
+
+pub fn list(cfg: &Config, filter: &Filter<'_>, default: bool, optional: bool) -> Result<(), Error> {
+    for (dir, select) in cfg.repos(filter).flatten() {
         let exist = Path::new(dir).is_dir();
-        let (mark, repo) = match select {
+        let (mark, repo, kind) = match select {
             Selection::Explicit(repo) => {
                 if !default {
                     continue;
                 } else {
-                    (if exist { "*" } else { "-" }, repo)
+                    (if exist { "*" } else { "-" }, repo, "explicit")
                 }
             }
             Selection::Optional(repo) => {
                 if !optional {
                     continue;
                 } else {
-                    (if exist { "o" } else { " " }, repo)
+                    (if exist { "o" } else { " " }, repo, "optional")
                 }
             }
         };
-        println!("{} {:<19} {}", print::good(mark), dir, repo.url());
+        if print::format() == Format::Text {
+            println!("{} {:<19} {}", print::good(mark), dir, repo.url());
+        } else {
+            print::record(json!({
+                "name": dir,
+                "url": repo.url(),
+                "selection": kind,
+                "exists": exist,
+            }));
+        }
     }
     Ok(())
 }
@@ -39,7 +50,15 @@ pub fn list_unknown(cfg: &Config) -> Result<(), Error> {
         let file_name = entry.file_name();
         let name = file_name.to_string_lossy();
         if !cfg.is_known(&name) && !name.starts_with('.') {
-            println!("{}", name);
+            if print::format() == Format::Text {
+                println!("{}", name);
+            } else {
+                print::record(json!({
+                    "name": name,
+                    "selection": "unknown",
+                    "exists": true,
+                }));
+            }
         }
     }
     Ok(())
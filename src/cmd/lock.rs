@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use anyhow::{Error, anyhow};
+use indexmap::IndexMap;
+use serde_json::json;
+
+use crate::config::{Config, Filter, Remote, Repo};
+use crate::git::Git;
+use crate::lock::{Lock, LockEntry, load_lock, save_lock};
+use crate::print::{self, Format};
+
+pub async fn update_lock<'a, I>(cfg: &'a Config, repos: I) -> Result<(), Error>
+where
+    I: Iterator<Item = (&'a str, &'a Repo)>,
+{
+    let Some(path) = cfg.lock_file() else {
+        return Ok(());
+    };
+    let old = load_lock(&path)?;
+    let mut updated = IndexMap::new();
+    for (name, repo) in repos {
+        let dir = Path::new(name);
+        if !dir.is_dir() {
+            continue;
+        }
+        match cfg.git().head(dir).await {
+            Ok(commit) => {
+                let url = repo.url();
+                updated.insert(name, LockEntry { url, commit });
+            }
+            Err(err) => print::info(err),
+        }
+    }
+    let mut lock = Lock::default();
+    for (name, _) in cfg.repos(&Filter::default()).flatten() {
+        if let Some(entry) = updated.swap_remove(name).or_else(|| old.get(name).cloned()) {
+            lock.insert(name, entry);
+        }
+    }
+    save_lock(path, &lock)
+}
+
+pub async fn lock(cfg: &Config, filter: &Filter<'_>, update: bool) -> Result<(), Error> {
+    let path = cfg
+        .lock_file()
+        .ok_or_else(|| anyhow!("Lock file is not available"))?;
+    if update {
+        let mut repos = vec![];
+        for result in cfg.repos(filter) {
+            match result {
+                Ok((name, select)) => repos.push((name, *select.repo())),
+                Err(err) => print::info(err),
+            }
+        }
+        update_lock(cfg, repos.into_iter()).await?;
+    }
+    let lock: Lock = load_lock(&path)?;
+    for (name, _) in cfg.repos(filter).flatten() {
+        let Some(entry) = lock.get(name) else {
+            continue;
+        };
+        let dir = Path::new(name);
+        let (mark, state) = if !dir.is_dir() {
+            ("-", "missing")
+        } else if cfg.git().head(dir).await.ok().as_ref() == Some(&entry.commit) {
+            ("*", "locked")
+        } else {
+            ("!", "differs")
+        };
+        if print::format() == Format::Text {
+            println!("{} {:<19} {}", print::good(mark), name, entry.commit);
+        } else {
+            print::record(json!({
+                "name": name,
+                "operation": "lock",
+                "url": entry.url,
+                "commit": entry.commit,
+                "state": state,
+            }));
+        }
+    }
+    Ok(())
+}
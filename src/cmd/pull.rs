@@ -1,38 +1,146 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use anyhow::Error;
-use tokio::sync::Semaphore;
 
-use super::common::{bounded_run, join_handles};
-use crate::config::Config;
-use crate::git::Git;
+use super::common::{Cancelled, Limiter, bounded_run, join_handles, repo_info};
+use super::summary::{Outcome, Report, Summary};
+use crate::config::{Config, RepoOptions, Strategy};
+use crate::git::{Git, GitError, RepoInfo, run_hooks};
 use crate::print;
 
-pub async fn pull<'a, I>(cfg: &Config, dirs: I) -> Result<(), Error>
+/// Pulls the repo, setting `stash_pending` while autostashed changes are not
+/// reapplied yet.
+pub(super) async fn pull_one<'a, G>(
+    git: &'a G,
+    dir: &Path,
+    opts: &RepoOptions,
+    force: bool,
+    stash_pending: &AtomicBool,
+    info: RepoInfo,
+) -> Result<Report, Error>
+where
+    G: Git<'a>,
+{
+    let check_reset = opts.strategy == Strategy::Reset && !force;
+    let dirty = (opts.autostash || check_reset) && git.status(dir).await?.changed > 0;
+    let stashed = opts.autostash && dirty;
+    if check_reset && dirty && !stashed {
+        return Err(GitError {
+            key: dir.to_string_lossy().into_owned(),
+            msg: "Local changes would be lost by reset, use --force to discard them".to_string(),
+            retries: 0,
+        }
+        .into());
+    }
+    let old = git.head(dir).await?;
+    if stashed {
+        git.stash_push(dir).await?;
+        stash_pending.store(true, Ordering::SeqCst);
+    }
+    let result = git.pull(dir, opts, info).await;
+    if result.is_err() && matches!(opts.strategy, Strategy::Rebase | Strategy::Merge) {
+        let _ = git.abort_update(dir, opts).await;
+    }
+    let stash_conflict = stashed && git.stash_pop(dir).await.is_err();
+    stash_pending.store(false, Ordering::SeqCst);
+    let finished = result?;
+    let new = git.head(dir).await?;
+    let mut hook_failure = None;
+    let outcome = if old == new {
+        Outcome::UpToDate
+    } else {
+        let commits = git.count_commits(dir, &old, &new).await?.parse()?;
+        hook_failure = run_post_hooks(dir, "post-update", &opts.post_update).await;
+        if commits == 0 {
+            let commits = git.count_commits(dir, &new, &old).await?.parse()?;
+            Outcome::Reverted { old, new, commits }
+        } else {
+            Outcome::Updated { old, new, commits }
+        }
+    };
+    Ok(Report {
+        key: finished.key,
+        outcome,
+        stash_conflict,
+        hook_failure,
+        retries: finished.retries,
+    })
+}
+
+/// Runs hooks after the repo changed, returning the failure to report along
+/// with the change instead of failing the repo.
+pub(super) async fn run_post_hooks(dir: &Path, op: &str, hooks: &[String]) -> Option<String> {
+    let err = run_hooks(dir, op, hooks).await.err()?;
+    let msg = match err.downcast::<GitError>() {
+        Ok(err) => err.msg,
+        Err(err) => err.to_string(),
+    };
+    Some(format!("{} hook failed, {}", op, msg))
+}
+
+/// Records the repo in the cancellation error if autostashed changes were left
+/// in the stash.
+pub(super) fn check_stash_left(
+    result: Result<Report, Error>,
+    dir: &Path,
+    stash_pending: &AtomicBool,
+) -> Result<Report, Error> {
+    match result {
+        Err(err) if err.is::<Cancelled>() && stash_pending.load(Ordering::SeqCst) => {
+            Err(Cancelled {
+                stash_left: Some(dir.to_string_lossy().into_owned()),
+            }
+            .into())
+        }
+        result => result,
+    }
+}
+
+pub async fn pull<'a, I>(cfg: &Config, dirs: I, force: bool) -> Result<(), Error>
 where
     I: Iterator<Item = &'a str>,
 {
-    let sem = Arc::new(Semaphore::new(cfg.concurrency()));
+    let limiter = Limiter::new(cfg.concurrency());
     let mut handles = vec![];
+    let mut summary = Summary::default();
     for dir in dirs {
-        let sem = Arc::clone(&sem);
+        let limiter = Arc::clone(&limiter);
         let path = PathBuf::from(dir);
         if !path.is_dir() {
-            println!("{}: No such directory", print::warn(dir));
+            print::info(format_args!("{}: No such directory", print::warn(dir)));
+            summary.skip(dir, "no such directory");
             continue;
         }
         let mut git_path = path.clone();
         git_path.push(".git");
         if !git_path.exists() {
-            println!("{}: Not git repository", print::warn(dir));
+            print::info(format_args!("{}: Not git repository", print::warn(dir)));
+            summary.skip(dir, "not git repository");
             continue;
         }
         let git = cfg.git().clone();
+        let opts = cfg.options(dir).clone();
+        let info = repo_info(cfg, dir);
         let path = PathBuf::from(&dir);
-        handles.push(tokio::spawn(async move {
-            bounded_run(git.pull(&path), sem).await
-        }));
+        handles.push((
+            dir.to_string(),
+            tokio::spawn(async move {
+                let stash_pending = AtomicBool::new(false);
+                let result = bounded_run(
+                    pull_one(&git, &path, &opts, force, &stash_pending, info),
+                    limiter,
+                )
+                .await;
+                check_stash_left(result, &path, &stash_pending)
+            }),
+        ));
     }
-    join_handles("pull", handles).await
+    let total = handles.len();
+    let pulled = join_handles("pull", handles).await?;
+    let result = pulled.check_cancelled();
+    summary.add_joined(total, pulled);
+    summary.print();
+    result
 }
@@ -0,0 +1,59 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Error, anyhow};
+use serde_json::json;
+
+use crate::config::{self, Config};
+use crate::git::Git;
+use crate::print;
+
+async fn local_changes<'a, G>(git: &'a G, dir: &Path) -> Result<Vec<&'static str>, Error>
+where
+    G: Git<'a>,
+{
+    let mut changes = vec![];
+    if !git.status(dir).await?.is_clean() {
+        changes.push("uncommitted changes");
+    }
+    if git.unpushed(dir).await? != "0" {
+        changes.push("unpushed commits");
+    }
+    if !git.stash_list(dir).await?.is_empty() {
+        changes.push("stashes");
+    }
+    Ok(changes)
+}
+
+pub async fn remove(cfg: &Config, name: &str, keep_dir: bool, force: bool) -> Result<(), Error> {
+    if !cfg.is_known(name) {
+        return Err(anyhow!("{} is not a repo in config", print::warn(name)));
+    }
+    let dir = Path::new(name);
+    let remove_dir = !keep_dir && dir.is_dir();
+    if remove_dir && !force {
+        let changes = local_changes(cfg.git(), dir).await?;
+        if !changes.is_empty() {
+            return Err(anyhow!(
+                "{} has {}, use --force to remove anyway",
+                print::warn(name),
+                changes.join(", ")
+            ));
+        }
+    }
+    let path = cfg
+        .file()
+        .ok_or_else(|| anyhow!("Config file is not available"))?;
+    config::remove_repo(path, name)?;
+    print::info(format_args!("{}: Removed from config", print::good(name)));
+    if remove_dir {
+        fs::remove_dir_all(dir)?;
+        print::info(format_args!("{}: Deleted directory", print::good(name)));
+    }
+    print::record(json!({
+        "name": name,
+        "operation": "remove",
+        "deleted": remove_dir,
+    }));
+    Ok(())
+}
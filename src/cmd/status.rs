@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Error;
+use futures::future;
+use serde_json::json;
+
+use super::common::{Cancelled, Limiter, bounded_run};
+use crate::config::{Config, Filter, Remote, Selection};
+use crate::git::{Git, Status};
+use crate::print::{self, Format};
+
+fn describe(st: &Status, url: &str) -> String {
+    let mut desc = match &st.branch {
+        Some(branch) => branch.clone(),
+        None => format!("(detached {})", &st.oid[..st.oid.len().min(7)]),
+    };
+    if st.upstream.is_some() {
+        if st.ahead > 0 {
+            desc.push_str(&format!(", ahead {}", st.ahead));
+        }
+        if st.behind > 0 {
+            desc.push_str(&format!(", behind {}", st.behind));
+        }
+    }
+    if st.changed > 0 {
+        desc.push_str(&format!(", {} changed", st.changed));
+    }
+    if st.untracked > 0 {
+        desc.push_str(&format!(", {} untracked", st.untracked));
+    }
+    match &st.origin {
+        Some(origin) if origin == url => {}
+        Some(origin) => desc.push_str(&format!(", origin {}", origin)),
+        None => desc.push_str(", no origin"),
+    }
+    desc
+}
+
+pub async fn status(cfg: &Config, filter: &Filter<'_>) -> Result<(), Error> {
+    let limiter = Limiter::new(cfg.concurrency());
+    let mut handles = vec![];
+    for result in cfg.repos(filter) {
+        match result {
+            Ok((dir, select)) => {
+                if !Path::new(dir).is_dir() {
+                    if let Selection::Explicit(repo) = select {
+                        if print::format() == Format::Text {
+                            println!("{}: Not cloned", print::warn(dir));
+                        } else {
+                            print::record(json!({
+                                "name": dir,
+                                "operation": "status",
+                                "url": repo.url(),
+                                "exists": false,
+                            }));
+                        }
+                    }
+                    continue;
+                }
+                let limiter = Arc::clone(&limiter);
+                let path = PathBuf::from(&dir);
+                let git = cfg.git().clone();
+                let name = dir.to_string();
+                let url = select.repo().url();
+                handles.push(tokio::spawn(async move {
+                    let result = bounded_run(git.status(&path), limiter).await;
+                    (name, url, result)
+                }));
+            }
+            Err(err) => {
+                print::info(err);
+            }
+        }
+    }
+    let mut cancelled = false;
+    for result in future::join_all(handles).await {
+        let (name, url, result) = result?;
+        match result {
+            Ok(st) => {
+                let ok = st.is_clean() && st.ahead == 0 && st.origin.as_ref() == Some(&url);
+                if print::format() == Format::Text {
+                    let colorize = if ok { print::good } else { print::warn };
+                    println!("{:<19} {}", colorize(&name), describe(&st, &url));
+                } else {
+                    print::record(json!({
+                        "name": name,
+                        "operation": "status",
+                        "url": url,
+                        "exists": true,
+                        "clean": st.is_clean(),
+                        "branch": st.branch,
+                        "oid": st.oid,
+                        "upstream": st.upstream,
+                        "ahead": st.ahead,
+                        "behind": st.behind,
+                        "changed": st.changed,
+                        "untracked": st.untracked,
+                        "origin": st.origin,
+                    }));
+                }
+            }
+            Err(err) if err.is::<Cancelled>() => cancelled = true,
+            Err(err) => print::info(print::warn(&err.to_string())),
+        }
+    }
+    if cancelled {
+        return Err(Cancelled::default().into());
+    }
+    Ok(())
+}
@@ -0,0 +1,274 @@
+use serde_json::{Value, json};
+
+use super::common::Joined;
+use crate::git::Finished;
+use crate::print::{self, Format};
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Cloned,
+    Updated {
+        old: String,
+        new: String,
+        commits: usize,
+    },
+    /// HEAD moved back to an ancestor, such as by sync --locked.
+    Reverted {
+        old: String,
+        new: String,
+        commits: usize,
+    },
+    UpToDate,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub key: String,
+    pub outcome: Outcome,
+    /// True if reapplying autostashed changes conflicted.
+    pub stash_conflict: bool,
+    /// Error of a post-clone or post-update hook run after the repo changed.
+    pub hook_failure: Option<String>,
+    pub retries: u32,
+}
+
+impl Report {
+    pub fn new(finished: Finished, outcome: Outcome) -> Self {
+        Report {
+            key: finished.key,
+            outcome,
+            stash_conflict: false,
+            hook_failure: None,
+            retries: finished.retries,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    cloned: Vec<String>,
+    updated: Vec<(String, String, String, usize)>,
+    reverted: Vec<(String, String, String, usize)>,
+    up_to_date: Vec<String>,
+    skipped: Vec<(String, &'static str)>,
+    stash_conflicts: Vec<String>,
+    hook_failures: Vec<(String, String)>,
+    failed: usize,
+    cancelled: usize,
+    stash_left: Vec<String>,
+}
+
+fn short(oid: &str) -> &str {
+    &oid[..oid.len().min(7)]
+}
+
+impl Summary {
+    pub fn add(&mut self, report: Report) {
+        let name = report.key;
+        if report.stash_conflict {
+            self.stash_conflicts.push(name.clone());
+        }
+        if let Some(msg) = report.hook_failure {
+            self.hook_failures.push((name.clone(), msg));
+        }
+        match report.outcome {
+            Outcome::Cloned => self.cloned.push(name),
+            Outcome::Updated { old, new, commits } => self.updated.push((name, old, new, commits)),
+            Outcome::Reverted { old, new, commits } => {
+                self.reverted.push((name, old, new, commits))
+            }
+            Outcome::UpToDate => self.up_to_date.push(name),
+        }
+    }
+
+    pub fn skip(&mut self, name: &str, reason: &'static str) {
+        self.skipped.push((name.to_string(), reason));
+    }
+
+    /// Adds results of `total` joined tasks, counting tasks which neither
+    /// succeeded nor were cancelled as failed.
+    pub fn add_joined(&mut self, total: usize, joined: Joined<Report>) {
+        self.failed += total - joined.succeeded.len() - joined.cancelled;
+        self.cancelled += joined.cancelled;
+        self.stash_left.extend(joined.stash_left);
+        for report in joined.succeeded {
+            self.add(report);
+        }
+    }
+
+    fn record(&self) -> Value {
+        json!({
+            "operation": "summary",
+            "cloned": self.cloned,
+            "updated": self.updated.iter().map(|(name, old, new, commits)| json!({
+                "name": name,
+                "old": old,
+                "new": new,
+                "commits": commits,
+            })).collect::<Vec<_>>(),
+            "reverted": self.reverted.iter().map(|(name, old, new, commits)| json!({
+                "name": name,
+                "old": old,
+                "new": new,
+                "commits": commits,
+            })).collect::<Vec<_>>(),
+            "up_to_date": self.up_to_date,
+            "skipped": self.skipped.iter().map(|(name, reason)| json!({
+                "name": name,
+                "reason": reason,
+            })).collect::<Vec<_>>(),
+            "stash_conflicts": self.stash_conflicts,
+            "hook_failures": self.hook_failures.iter().map(|(name, msg)| json!({
+                "name": name,
+                "error": msg,
+            })).collect::<Vec<_>>(),
+            "failed": self.failed,
+            "cancelled": self.cancelled,
+            "stash_left": self.stash_left,
+        })
+    }
+
+    pub fn print(&self) {
+        if print::format() != Format::Text {
+            print::record(self.record());
+            return;
+        }
+        print::info(format_args!(
+            "\nSummary: {} cloned, {} updated, {} reverted, {} up to date, {} skipped, {} failed, {} cancelled",
+            self.cloned.len(),
+            self.updated.len(),
+            self.reverted.len(),
+            self.up_to_date.len(),
+            self.skipped.len(),
+            self.failed,
+            self.cancelled
+        ));
+        for name in &self.cloned {
+            print::info(format_args!("  {:<19} cloned", print::good(name)));
+        }
+        for (name, old, new, commits) in &self.updated {
+            print::info(format_args!(
+                "  {:<19} {}..{} ({} commit{})",
+                print::good(name),
+                short(old),
+                short(new),
+                commits,
+                if *commits == 1 { "" } else { "s" }
+            ));
+        }
+        for (name, old, new, commits) in &self.reverted {
+            print::info(format_args!(
+                "  {:<19} {}..{} ({} commit{} back)",
+                print::warn(name),
+                short(old),
+                short(new),
+                commits,
+                if *commits == 1 { "" } else { "s" }
+            ));
+        }
+        for (name, reason) in &self.skipped {
+            print::info(format_args!(
+                "  {:<19} skipped, {}",
+                print::warn(name),
+                reason
+            ));
+        }
+        for name in &self.stash_conflicts {
+            print::info(format_args!(
+                "  {:<19} autostash conflicted, changes are kept in stash",
+                print::warn(name)
+            ));
+        }
+        for name in &self.stash_left {
+            print::info(format_args!(
+                "  {:<19} cancelled, autostashed changes are kept in stash",
+                print::warn(name)
+            ));
+        }
+        for (name, msg) in &self.hook_failures {
+            print::info(format_args!("  {:<19} {}", print::warn(name), msg));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cmd::summary::*;
+
+    fn report(key: &str, outcome: Outcome) -> Report {
+        Report::new(
+            Finished {
+                key: key.to_string(),
+                retries: 0,
+            },
+            outcome,
+        )
+    }
+
+    #[test]
+    fn test_summary_add_joined() {
+        let mut summary = Summary::default();
+        summary.skip("e", "optional, missing");
+        let mut updated = report(
+            "b",
+            Outcome::Updated {
+                old: "1111111aaa".to_string(),
+                new: "2222222bbb".to_string(),
+                commits: 2,
+            },
+        );
+        updated.stash_conflict = true;
+        let joined = Joined {
+            succeeded: vec![
+                report("a", Outcome::Cloned),
+                updated,
+                report(
+                    "c",
+                    Outcome::Reverted {
+                        old: "2222222bbb".to_string(),
+                        new: "1111111aaa".to_string(),
+                        commits: 2,
+                    },
+                ),
+                report("d", Outcome::UpToDate),
+            ],
+            cancelled: 2,
+            stash_left: vec!["g".to_string()],
+        };
+        summary.add_joined(7, joined);
+
+        assert_eq!(summary.cloned, vec!["a"]);
+        assert_eq!(summary.updated.len(), 1);
+        assert_eq!(summary.reverted.len(), 1);
+        assert_eq!(summary.up_to_date, vec!["d"]);
+        assert_eq!(summary.skipped.len(), 1);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.cancelled, 2);
+        assert_eq!(
+            summary.record(),
+            json!({
+                "operation": "summary",
+                "cloned": ["a"],
+                "updated": [{
+                    "name": "b",
+                    "old": "1111111aaa",
+                    "new": "2222222bbb",
+                    "commits": 2,
+                }],
+                "reverted": [{
+                    "name": "c",
+                    "old": "2222222bbb",
+                    "new": "1111111aaa",
+                    "commits": 2,
+                }],
+                "up_to_date": ["d"],
+                "skipped": [{"name": "e", "reason": "optional, missing"}],
+                "stash_conflicts": ["b"],
+                "hook_failures": [],
+                "failed": 1,
+                "cancelled": 2,
+                "stash_left": ["g"],
+            })
+        );
+    }
+}
@@ -1,28 +1,105 @@
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
-use anyhow::Error;
-use tokio::sync::Semaphore;
+use anyhow::{Error, anyhow};
+use serde_json::json;
 
-use super::common::{bounded_run, join_handles};
-use crate::config::{Config, Repo, Selection};
-use crate::git::{Git, GitResult};
+use super::common::{Cancelled, Limiter, bounded_run, join_handles, repo_info};
+use super::lock::update_lock;
+use super::pull::{check_stash_left, pull_one, run_post_hooks};
+use super::summary::{Outcome, Report, Summary};
+use crate::config::{Config, Filter, Log, Remote, Repo, RepoOptions, Revision, Selection};
+use crate::git::{Git, RepoInfo, staging_dir};
+use crate::lock::load_lock;
+use crate::print::{self, Format};
 
-async fn sync_one<'a, G>(git: &'a G, dir: &Path, repo: &Repo) -> GitResult
+async fn sync_one<'a, G>(
+    git: &'a G,
+    dir: &Path,
+    repo: &Repo,
+    opts: &RepoOptions,
+    force: bool,
+    stash_pending: &AtomicBool,
+    info: RepoInfo,
+) -> Result<Report, Error>
 where
     G: Git<'a>,
 {
     if dir.is_dir() {
-        git.pull(dir).await
+        pull_one(git, dir, opts, force, stash_pending, info).await
     } else {
-        git.cloner(dir, repo).await
+        let finished = git.cloner(dir, repo, opts, info).await?;
+        let mut report = Report::new(finished, Outcome::Cloned);
+        report.hook_failure = run_post_hooks(dir, "post-clone", &opts.post_clone).await;
+        Ok(report)
     }
 }
 
-pub async fn sync(cfg: &Config, names: Option<&Vec<&str>>) -> Result<(), Error> {
-    let sem = Arc::new(Semaphore::new(cfg.concurrency()));
+fn remove_partial_clone(path: &Path) {
+    if let Err(err) = fs::remove_dir_all(path) {
+        print::info(format_args!(
+            "{}: Unable to remove partially cloned directory, {}",
+            print::warn(&path.to_string_lossy()),
+            err
+        ));
+    }
+}
+
+async fn print_log(cfg: &Config, updated: &[(&str, &str, &str, usize)], log: Log) {
+    let limit = match log {
+        Log::Off => return,
+        Log::All => None,
+        Log::Limit(n) => Some(n),
+    };
+    for (name, old, new, commits) in updated {
+        let lines = match cfg.git().log(Path::new(name), old, new, limit).await {
+            Ok(lines) => lines,
+            Err(err) => {
+                print::info(print::warn(&err.to_string()));
+                continue;
+            }
+        };
+        if print::format() != Format::Text {
+            print::record(json!({
+                "name": name,
+                "operation": "log",
+                "commits": commits,
+                "log": lines.lines().collect::<Vec<_>>(),
+            }));
+            continue;
+        }
+        print::info(format_args!("\n{} ({} new)", print::good(name), commits));
+        for line in lines.lines() {
+            print::info(format_args!("  {}", line));
+        }
+        let shown = lines.lines().count();
+        if shown < *commits {
+            print::info(format_args!("  ... and {} more", commits - shown));
+        }
+    }
+}
+
+pub async fn sync(
+    cfg: &Config,
+    filter: &Filter<'_>,
+    locked: bool,
+    log: Log,
+    force: bool,
+) -> Result<(), Error> {
+    let lock = if locked {
+        let path = cfg
+            .lock_file()
+            .ok_or_else(|| anyhow!("Lock file is not available"))?;
+        Some(load_lock(path)?)
+    } else {
+        None
+    };
+    let limiter = Limiter::new(cfg.concurrency());
     let mut handles = vec![];
-    for result in cfg.repos(names) {
+    let mut summary = Summary::default();
+    for result in cfg.repos(filter) {
         match result {
             Ok((dir, select)) => {
                 let repo = match select {
@@ -31,21 +108,76 @@ pub async fn sync(cfg: &Config, names: Option<&Vec<&str>>) -> Result<(), Error>
                         if Path::new(dir).is_dir() {
                             repo.clone()
                         } else {
+                            summary.skip(dir, "optional, missing");
                             continue;
                         }
                     }
                 };
-                let sem = Arc::clone(&sem);
+                let mut opts = cfg.options(dir).clone();
+                if let Some(lock) = &lock {
+                    match lock.get(dir) {
+                        Some(entry) if entry.url == repo.url() => {
+                            opts.rev = Some(Revision::Rev(entry.commit.clone()));
+                        }
+                        _ => {
+                            print::info(format_args!("{}: Not locked", print::warn(dir)));
+                            summary.skip(dir, "not locked");
+                            continue;
+                        }
+                    }
+                }
+                let limiter = Arc::clone(&limiter);
                 let path = PathBuf::from(&dir);
                 let git = cfg.git().clone();
-                handles.push(tokio::spawn(async move {
-                    bounded_run(sync_one(&git, &path, &repo), sem).await
-                }));
+                let info = repo_info(cfg, dir);
+                handles.push((
+                    dir.to_string(),
+                    tokio::spawn(async move {
+                        let stash_pending = AtomicBool::new(false);
+                        let result = bounded_run(
+                            sync_one(&git, &path, &repo, &opts, force, &stash_pending, info),
+                            limiter,
+                        )
+                        .await;
+                        let staging = staging_dir(&path);
+                        if let Err(err) = &result
+                            && err.is::<Cancelled>()
+                            && staging.exists()
+                        {
+                            remove_partial_clone(&staging);
+                        }
+                        check_stash_left(result, &path, &stash_pending)
+                    }),
+                ));
             }
             Err(err) => {
-                println!("{}", err);
+                print::info(err);
             }
         }
     }
-    join_handles("sync", handles).await
+    let total = handles.len();
+    let joined = join_handles("sync", handles).await?;
+    let result = joined.check_cancelled();
+    let succeeded = &joined.succeeded;
+    if !locked {
+        let repos = cfg
+            .repos(filter)
+            .flatten()
+            .filter(|(name, _)| succeeded.iter().any(|report| report.key == *name))
+            .map(|(name, select)| (name, *select.repo()));
+        update_lock(cfg, repos).await?;
+    }
+    let updated = succeeded
+        .iter()
+        .filter_map(|report| match &report.outcome {
+            Outcome::Updated { old, new, commits } => {
+                Some((report.key.as_str(), old.as_str(), new.as_str(), *commits))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    print_log(cfg, &updated, log).await;
+    summary.add_joined(total, joined);
+    summary.print();
+    result
 }
@@ -0,0 +1,57 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Error;
+
+use super::common::{Limiter, bounded_run, join_handles, repo_info};
+use crate::config::{Config, Filter, RepoOptions};
+use crate::git::{Finished, Git, RepoInfo};
+use crate::print;
+
+async fn unshallow_one<'a, G>(
+    git: &'a G,
+    dir: &Path,
+    opts: &RepoOptions,
+    info: RepoInfo,
+) -> Result<Option<Finished>, Error>
+where
+    G: Git<'a>,
+{
+    if git.is_shallow(dir).await? != "true" {
+        return Ok(None);
+    }
+    Ok(Some(git.unshallow(dir, opts, info).await?))
+}
+
+pub async fn unshallow(cfg: &Config, filter: &Filter<'_>) -> Result<(), Error> {
+    let limiter = Limiter::new(cfg.concurrency());
+    let mut handles = vec![];
+    for result in cfg.repos(filter) {
+        match result {
+            Ok((dir, _)) => {
+                if !Path::new(dir).is_dir() {
+                    continue;
+                }
+                let limiter = Arc::clone(&limiter);
+                let path = PathBuf::from(&dir);
+                let git = cfg.git().clone();
+                let opts = cfg.options(dir).clone();
+                let info = repo_info(cfg, dir);
+                handles.push((
+                    dir.to_string(),
+                    tokio::spawn(async move {
+                        bounded_run(unshallow_one(&git, &path, &opts, info), limiter).await
+                    }),
+                ));
+            }
+            Err(err) => {
+                print::info(err);
+            }
+        }
+    }
+    let unshallowed = join_handles("unshallow", handles).await?;
+    if unshallowed.succeeded.iter().all(Option::is_none) {
+        print::info("No shallow repos.");
+    }
+    unshallowed.check_cancelled()
+}
@@ -1,28 +1,49 @@
 use std::convert::TryFrom;
 use std::fmt;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::slice;
+use std::time::Duration;
+use std::vec;
 
 use anyhow::Error;
+use glob::Pattern;
 use indexmap::{self, IndexMap};
+use regex::Regex;
 
+mod edit;
 mod internal;
 mod types;
-use self::internal::{Concurrency, ConfigInternal};
-pub use self::types::{GitCmd, GitHub, Remote, Repo, Selection};
+use self::internal::{Concurrency, ConfigError, ConfigInternal};
+pub use self::types::{
+    Bitbucket, Codeberg, GitCmd, GitHub, GitLab, GitUrl, Log, Protocol, Remote, Repo, RepoOptions,
+    Retry, Revision, Selection, SourceHut, Strategy, Submodules,
+};
 use crate::print;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Config {
+    file: Option<PathBuf>,
     git: GitCmd,
     dir: Option<PathBuf>,
     concur: Concurrency,
+    log: Log,
     repos: IndexMap<String, Selection<Repo>>,
+    opts: IndexMap<String, RepoOptions>,
+    defaults: RepoOptions,
 }
 
 impl Config {
+    pub fn file(&self) -> Option<&PathBuf> {
+        self.file.as_ref()
+    }
+
+    pub fn lock_file(&self) -> Option<PathBuf> {
+        self.file
+            .as_ref()
+            .map(|file| file.with_file_name(".gitcop.lock"))
+    }
+
     pub fn git(&self) -> &GitCmd {
         &self.git
     }
@@ -35,31 +56,131 @@ impl Config {
         self.concur.value() as usize
     }
 
+    pub fn log(&self) -> Log {
+        self.log
+    }
+
     pub fn is_known(&self, name: &str) -> bool {
         self.repos.contains_key(name)
     }
 
-    pub fn repos<'a>(&'a self, names: Option<&'a Vec<&'a str>>) -> ReposIter<'a> {
-        if let Some(names) = names {
+    pub fn selection(&self, name: &str) -> Option<&Selection<Repo>> {
+        self.repos.get(name)
+    }
+
+    pub fn options(&self, name: &str) -> &RepoOptions {
+        self.opts.get(name).unwrap_or(&self.defaults)
+    }
+
+    /// Overrides timeout of all repos, such as by command line option.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.defaults.timeout = Some(timeout);
+        for opts in self.opts.values_mut() {
+            opts.timeout = Some(timeout);
+        }
+    }
+
+    /// Overrides update strategy of all repos, such as by command line option.
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.defaults.strategy = strategy;
+        for opts in self.opts.values_mut() {
+            opts.strategy = strategy;
+        }
+    }
+
+    /// Enables autostash of all repos, such as by command line option.
+    pub fn set_autostash(&mut self) {
+        self.defaults.autostash = true;
+        for opts in self.opts.values_mut() {
+            opts.autostash = true;
+        }
+    }
+
+    pub fn has_tag(&self, name: &str, tags: &[&str]) -> bool {
+        self.options(name)
+            .tags
+            .iter()
+            .any(|tag| tags.contains(&tag.as_str()))
+    }
+
+    fn matches(&self, pattern: &str) -> Vec<(&String, &Selection<Repo>)> {
+        match Pattern::new(pattern) {
+            Ok(glob) if pattern.contains(['*', '?', '[']) => self
+                .repos
+                .iter()
+                .filter(|(name, _)| glob.matches(name))
+                .collect(),
+            _ => self.repos.get_key_value(pattern).into_iter().collect(),
+        }
+    }
+
+    fn select<'a>(&'a self, filter: &Filter<'a>) -> Vec<RepoItem<'a>> {
+        let mut items = vec![];
+        let mut add = |pattern: &str, matched: Vec<(&'a String, &'a Selection<Repo>)>| {
+            if matched.is_empty() {
+                items.push(Err(RepoNotFound {
+                    name: pattern.to_string(),
+                }));
+            }
+            for (name, sel) in matched {
+                let name = name.as_str();
+                let selected = items
+                    .iter()
+                    .any(|item| matches!(item, Ok((n, _)) if *n == name));
+                if !selected && !self.has_tag(name, &filter.exclude_tags) {
+                    items.push(Ok((name, Selection::Explicit(sel.repo()))));
+                }
+            }
+        };
+        for pattern in filter.names.iter().flatten() {
+            add(pattern, self.matches(pattern));
+        }
+        for re in &filter.regexes {
+            let matched = self
+                .repos
+                .iter()
+                .filter(|(name, _)| re.is_match(name))
+                .collect();
+            add(re.as_str(), matched);
+        }
+        items
+    }
+
+    pub fn repos<'a>(&'a self, filter: &'a Filter<'a>) -> ReposIter<'a> {
+        if filter.names.is_some() || !filter.regexes.is_empty() {
             ReposIter::Selected(ReposSelected {
-                cfg: self,
-                names: names.iter(),
+                iter: self.select(filter).into_iter(),
             })
         } else {
             ReposIter::All(ReposAll {
+                cfg: self,
                 iter: self.repos.iter(),
+                tags: &filter.tags,
+                exclude_tags: &filter.exclude_tags,
             })
         }
     }
 }
 
+#[derive(Debug, Default)]
+pub struct Filter<'a> {
+    pub names: Option<Vec<&'a str>>,
+    pub regexes: Vec<Regex>,
+    pub tags: Vec<&'a str>,
+    pub exclude_tags: Vec<&'a str>,
+}
+
+type RepoItem<'a> = Result<(&'a str, Selection<&'a Repo>), RepoNotFound>;
+
 pub struct ReposAll<'a> {
+    cfg: &'a Config,
     iter: indexmap::map::Iter<'a, String, Selection<Repo>>,
+    tags: &'a [&'a str],
+    exclude_tags: &'a [&'a str],
 }
 
 pub struct ReposSelected<'a> {
-    cfg: &'a Config,
-    names: slice::Iter<'a, &'a str>,
+    iter: vec::IntoIter<RepoItem<'a>>,
 }
 
 pub enum ReposIter<'a> {
@@ -81,29 +202,22 @@ impl fmt::Display for RepoNotFound {
 }
 
 impl<'a> Iterator for ReposIter<'a> {
-    type Item = Result<(&'a str, Selection<&'a Repo>), RepoNotFound>;
+    type Item = RepoItem<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self {
-            ReposIter::Selected(repo_sel) => {
-                if let Some(n) = repo_sel.names.next() {
-                    if let Some(sel) = repo_sel.cfg.repos.get(*n) {
-                        if let Selection::Optional(repo) = sel {
-                            return Some(Ok((n, Selection::Explicit(repo))));
-                        } else {
-                            return Some(Ok((n, sel.as_ref())));
-                        }
-                    } else {
-                        return Some(Err(RepoNotFound {
-                            name: n.to_string(),
-                        }));
+            ReposIter::Selected(repo_sel) => repo_sel.iter.next(),
+            ReposIter::All(repo_all) => {
+                for (s, repo) in repo_all.iter.by_ref() {
+                    if repo_all.cfg.has_tag(s, repo_all.exclude_tags)
+                        || (!repo_all.tags.is_empty() && !repo_all.cfg.has_tag(s, repo_all.tags))
+                    {
+                        continue;
                     }
+                    return Some(Ok((s.as_ref(), repo.as_ref())));
                 }
                 None
             }
-            ReposIter::All(ReposAll { iter }) => {
-                iter.next().map(|(s, repo)| Ok((s.as_ref(), repo.as_ref())))
-            }
         }
     }
 }
@@ -112,38 +226,121 @@ pub fn load_config<P>(path: P) -> Result<Config, Error>
 where
     P: AsRef<Path>,
 {
-    let mut file = File::open(path)?;
+    let mut file = File::open(&path)?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
-    parse_config(&contents)
+    let mut cfg = parse_config(&contents)?;
+    let file = std::path::absolute(path)?;
+    let base = file.parent().unwrap_or(Path::new("/"));
+    cfg.dir = Some(match &cfg.dir {
+        Some(dir) => base.join(dir),
+        None => base.to_path_buf(),
+    });
+    cfg.file = Some(file);
+    Ok(cfg)
+}
+
+pub fn find_config<P>(start: P) -> Option<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    start
+        .as_ref()
+        .ancestors()
+        .map(|dir| dir.join(".gitcop.toml"))
+        .find(|file| file.is_file())
+}
+
+pub fn add_repo<P>(
+    path: P,
+    name: &str,
+    spec: &str,
+    type_: Option<&str>,
+    branch: Option<&str>,
+    optional: bool,
+) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+{
+    let contents = fs::read_to_string(&path)?;
+    let contents = edit::add_repo(&contents, name, spec, type_, branch, optional)?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+pub fn remove_repo<P>(path: P, name: &str) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+{
+    let contents = fs::read_to_string(&path)?;
+    let contents = edit::remove_repo(&contents, name)?;
+    fs::write(path, contents)?;
+    Ok(())
 }
 
 pub fn parse_config(s: &str) -> Result<Config, Error> {
     let cfgi = toml::from_str::<ConfigInternal>(s)?;
     let git = cfgi.git;
     let dir = cfgi.directory;
+    let defaults = RepoOptions {
+        retry: Retry {
+            count: cfgi.retries.unwrap_or_default(),
+            delay: cfgi
+                .retry_delay
+                .map_or(Retry::default().delay, Duration::from_secs),
+        },
+        timeout: cfgi.timeout.map(Duration::from_secs),
+        strategy: cfgi.update,
+        autostash: cfgi.autostash,
+        depth: cfgi.depth,
+        filter: cfgi.filter.clone(),
+        single_branch: cfgi.single_branch,
+        submodules: cfgi.submodules,
+        post_clone: cfgi.post_clone.clone(),
+        post_update: cfgi.post_update.clone(),
+        ..RepoOptions::default()
+    };
     let mut repo_map = IndexMap::new();
+    let mut opts_map: IndexMap<String, RepoOptions> = IndexMap::new();
     for (key, val) in &cfgi.repositories {
-        let repo = Repo::try_from((key.as_str(), val))?;
+        let repo = Repo::try_from((key.as_str(), val, cfgi.protocol))?;
         repo_map.insert(key.to_string(), Selection::Explicit(repo));
+        opts_map.insert(key.to_string(), RepoOptions::try_from((val, &defaults))?);
     }
     if let Some(opt_repos) = &cfgi.optional_repositories {
         for (key, val) in opt_repos {
-            let repo = Repo::try_from((key.as_str(), val))?;
+            let repo = Repo::try_from((key.as_str(), val, cfgi.protocol))?;
             repo_map.insert(key.to_string(), Selection::Optional(repo));
+            opts_map.insert(key.to_string(), RepoOptions::try_from((val, &defaults))?);
+        }
+    }
+    for (group, names) in &cfgi.groups {
+        for name in names {
+            let opts = opts_map
+                .get_mut(name)
+                .ok_or_else(|| ConfigError::UnknownGroupRepo {
+                    group: group.to_string(),
+                    name: name.to_string(),
+                })?;
+            if !opts.tags.contains(group) {
+                opts.tags.push(group.to_string());
+            }
         }
     }
     Ok(Config {
+        file: None,
         git,
         dir: dir.map(PathBuf::from),
         concur: cfgi.concurrency,
+        log: cfgi.log,
         repos: repo_map,
+        opts: opts_map,
+        defaults,
     })
 }
 
 #[cfg(test)]
 mod test {
-    use crate::config::internal::Concurrency;
     use crate::config::*;
 
     #[test]
@@ -168,22 +365,22 @@ repo = "magnars/dash.el"
         assert_eq!(cfg.dir(), None);
 
         let opt1 = cfg.repos.get("use-package");
-        assert_eq!(opt1.is_some(), true);
+        assert!(opt1.is_some());
         let repo1 = opt1.unwrap();
         assert_eq!(repo1.url(), "https://github.com/jweigley/use-package.git");
 
         let opt2 = cfg.repos.get("dash");
-        assert_eq!(opt2.is_some(), true);
+        assert!(opt2.is_some());
         let repo2 = opt2.unwrap();
         assert_eq!(repo2.url(), "https://github.com/magnars/dash.el.git");
 
         let opt3 = cfg.repos.get("f");
-        assert_eq!(opt3.is_some(), true);
+        assert!(opt3.is_some());
         let repo3 = opt3.unwrap();
         assert_eq!(repo3.url(), "https://github.com/rejeep/f.el.git");
 
         let opt4 = cfg.repos.get("s");
-        assert_eq!(opt4.is_some(), true);
+        assert!(opt4.is_some());
         let repo4 = opt4.unwrap();
         assert_eq!(repo4.url(), "https://github.com/magnars/s.el.git");
     }
@@ -199,12 +396,12 @@ dash = "magnars/dash.el"
         assert_eq!(cfg.dir(), None);
 
         let opt1 = cfg.repos.get("use-package");
-        assert_eq!(opt1.is_some(), true);
+        assert!(opt1.is_some());
         let repo1 = opt1.unwrap();
         assert_eq!(repo1.url(), "https://github.com/jweigley/use-package.git");
 
         let opt2 = cfg.repos.get("dash");
-        assert_eq!(opt2.is_some(), true);
+        assert!(opt2.is_some());
         let repo2 = opt2.unwrap();
         assert_eq!(repo2.url(), "https://github.com/magnars/dash.el.git");
     }
@@ -220,7 +417,7 @@ use-package = "jweigley"
         assert_eq!(cfg.dir(), Some(&PathBuf::from("repos")));
 
         let opt1 = cfg.repos.get("use-package");
-        assert_eq!(opt1.is_some(), true);
+        assert!(opt1.is_some());
         let repo1 = opt1.unwrap();
         assert_eq!(repo1.url(), "https://github.com/jweigley/use-package.git");
     }
@@ -238,17 +435,17 @@ magit = "magit"
         assert_eq!(cfg.dir(), None);
 
         let opt1 = cfg.repos.get("use-package");
-        assert_eq!(opt1.is_some(), true);
+        assert!(opt1.is_some());
         let repo1 = opt1.unwrap();
         assert_eq!(repo1.url(), "https://github.com/jweigley/use-package.git");
 
         let opt2 = cfg.repos.get("magit");
-        assert_eq!(opt2.is_some(), true);
+        assert!(opt2.is_some());
         let repo2 = opt2.unwrap();
         assert_eq!(repo2.url(), "https://github.com/magit/magit.git");
 
         let opt3 = cfg.repos.get("forge");
-        assert_eq!(opt3.is_some(), true);
+        assert!(opt3.is_some());
         let repo3 = opt3.unwrap();
         assert_eq!(repo3.url(), "https://github.com/magit/forge.git");
     }
@@ -270,16 +467,16 @@ concurrency = 123
     #[test]
     fn test_parse_config_with_invalid_concur() {
         let result = parse_config("concurrency = -1\n[repositories]");
-        assert_eq!(result.is_err(), true);
+        assert!(result.is_err());
 
         let result = parse_config("concurrency = 0\n[repositories]");
-        assert_eq!(result.is_err(), true);
+        assert!(result.is_err());
 
         let result = parse_config("concurrency = 65536\n[repositories]");
-        assert_eq!(result.is_err(), true);
+        assert!(result.is_err());
 
         let result = parse_config("concurrency = NaN\n[repositories]");
-        assert_eq!(result.is_err(), true);
+        assert!(result.is_err());
     }
 
     #[test]
@@ -287,7 +484,7 @@ concurrency = 123
         let s = r#"repositories.foo = "bar/baz/foo""#;
         let result = parse_config(s);
 
-        assert_eq!(result.is_err(), true);
+        assert!(result.is_err());
         assert_eq!(
             format!("{}", result.err().unwrap()),
             "invalid repo name: bar/baz/foo"
@@ -296,25 +493,336 @@ concurrency = 123
 
     #[test]
     fn test_parse_config_unknown_type() {
-        let s = r#"repositories.foo = { type = "bitbucket", repo = "bar/baz" }"#;
+        let s = r#"repositories.foo = { type = "svn", repo = "bar/baz" }"#;
         let result = parse_config(s);
 
-        assert_eq!(result.is_err(), true);
+        assert!(result.is_err());
+        assert_eq!(
+            format!("{}", result.err().unwrap()),
+            "unknown repo type: svn"
+        );
+    }
+
+    #[test]
+    fn test_parse_config_hosted_types() {
+        let s = r#"[repositories]
+a = { type = "gitlab", repo = "foo/a.el" }
+b = { type = "bitbucket", repo = "foo" }
+c = { type = "codeberg", repo = "foo/c" }
+d = { type = "sourcehut", repo = "foo/d.el" }
+"#;
+        let cfg = parse_config(s).unwrap();
+
+        assert_eq!(
+            cfg.repos.get("a").unwrap().url(),
+            "https://gitlab.com/foo/a.el.git"
+        );
+        assert_eq!(
+            cfg.repos.get("b").unwrap().url(),
+            "https://bitbucket.org/foo/b.git"
+        );
+        assert_eq!(
+            cfg.repos.get("c").unwrap().url(),
+            "https://codeberg.org/foo/c.git"
+        );
+        assert_eq!(
+            cfg.repos.get("d").unwrap().url(),
+            "https://git.sr.ht/~foo/d.el"
+        );
+    }
+
+    #[test]
+    fn test_parse_config_protocol() {
+        let s = r#"protocol = "ssh"
+[repositories]
+a = "foo/a.el"
+b = { type = "sourcehut", repo = "foo/b" }
+c = { type = "gitlab", repo = "foo/c", protocol = "https" }
+d = { url = "https://git.example.com/d.git" }
+"#;
+        let cfg = parse_config(s).unwrap();
+
+        assert_eq!(
+            cfg.repos.get("a").unwrap().url(),
+            "git@github.com:foo/a.el.git"
+        );
+        assert_eq!(cfg.repos.get("b").unwrap().url(), "git@git.sr.ht:~foo/b");
+        assert_eq!(
+            cfg.repos.get("c").unwrap().url(),
+            "https://gitlab.com/foo/c.git"
+        );
+        assert_eq!(
+            cfg.repos.get("d").unwrap().url(),
+            "https://git.example.com/d.git"
+        );
+
+        let result = parse_config("protocol = \"git\"\n[repositories]");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_config_invalid_repo_field() {
+        let err = parse_config("[repositories]\na = { repo = \"foo/a\", protocol = \"git\" }\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("unknown variant `git`"), "{}", err);
+        let err = parse_config("[repositories]\na = { repo = \"foo/a\", brnach = \"dev\" }\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("unknown field `brnach`"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_config_revision() {
+        let s = r#"[repositories]
+a = "foo/a.el"
+b = { type = "github", repo = "foo/b", branch = "develop" }
+c = { type = "gitlab", repo = "foo/c", tag = "v1.0" }
+d = { url = "https://git.example.com/d.git", rev = "0123abc" }
+"#;
+        let cfg = parse_config(s).unwrap();
+
+        assert_eq!(cfg.options("a").rev, None);
+        assert_eq!(
+            cfg.options("b").rev,
+            Some(Revision::Branch("develop".to_string()))
+        );
+        assert_eq!(
+            cfg.options("c").rev,
+            Some(Revision::Tag("v1.0".to_string()))
+        );
+        assert_eq!(
+            cfg.options("d").rev,
+            Some(Revision::Rev("0123abc".to_string()))
+        );
+        assert_eq!(cfg.options("unknown"), &RepoOptions::default());
+
+        let result = parse_config(
+            r#"repositories.foo = { type = "github", repo = "foo", branch = "a", tag = "b" }"#,
+        );
         assert_eq!(
             format!("{}", result.err().unwrap()),
-            "unknown repo type: bitbucket"
+            "only one of branch, tag or rev can be specified"
         );
     }
 
+    #[test]
+    fn test_parse_config_retry() {
+        let s = r#"retries = 3
+[repositories]
+a = "foo/a"
+b = { repo = "foo/b", retries = 1, retry-delay = 5 }
+"#;
+        let cfg = parse_config(s).unwrap();
+
+        assert_eq!(
+            cfg.options("a").retry,
+            Retry {
+                count: 3,
+                delay: Duration::from_secs(1)
+            }
+        );
+        assert_eq!(
+            cfg.options("b").retry,
+            Retry {
+                count: 1,
+                delay: Duration::from_secs(5)
+            }
+        );
+        assert_eq!(cfg.options("unknown").retry.count, 3);
+    }
+
+    #[test]
+    fn test_parse_config_timeout() {
+        let s = r#"timeout = 600
+[repositories]
+a = "foo/a"
+b = { repo = "foo/b", timeout = 60 }
+"#;
+        let mut cfg = parse_config(s).unwrap();
+
+        assert_eq!(cfg.options("a").timeout, Some(Duration::from_secs(600)));
+        assert_eq!(cfg.options("b").timeout, Some(Duration::from_secs(60)));
+
+        cfg.set_timeout(Duration::from_secs(5));
+        assert_eq!(cfg.options("a").timeout, Some(Duration::from_secs(5)));
+        assert_eq!(cfg.options("b").timeout, Some(Duration::from_secs(5)));
+        assert_eq!(cfg.options("unknown").timeout, Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_parse_config_update() {
+        let s = r#"update = "rebase"
+[repositories]
+a = "foo/a"
+b = { repo = "foo/b", update = "reset" }
+"#;
+        let mut cfg = parse_config(s).unwrap();
+
+        assert_eq!(cfg.options("a").strategy, Strategy::Rebase);
+        assert_eq!(cfg.options("b").strategy, Strategy::Reset);
+
+        cfg.set_strategy(Strategy::Merge);
+        assert_eq!(cfg.options("a").strategy, Strategy::Merge);
+        assert_eq!(cfg.options("b").strategy, Strategy::Merge);
+
+        let result = parse_config("update = \"squash\"\n[repositories]");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_config_autostash() {
+        let s = r#"autostash = true
+[repositories]
+a = "foo/a"
+b = { repo = "foo/b", autostash = false }
+"#;
+        let mut cfg = parse_config(s).unwrap();
+
+        assert!(cfg.options("a").autostash);
+        assert!(!cfg.options("b").autostash);
+
+        cfg.set_autostash();
+        assert!(cfg.options("b").autostash);
+    }
+
+    #[test]
+    fn test_parse_config_shallow() {
+        let s = r#"depth = 1
+[repositories]
+a = "foo/a"
+b = { repo = "foo/b", depth = 10, filter = "blob:none", single-branch = true }
+"#;
+        let cfg = parse_config(s).unwrap();
+
+        let opts = cfg.options("a");
+        assert_eq!(opts.depth, Some(1));
+        assert_eq!(opts.filter, None);
+        assert!(!opts.single_branch);
+
+        let opts = cfg.options("b");
+        assert_eq!(opts.depth, Some(10));
+        assert_eq!(opts.filter, Some("blob:none".to_string()));
+        assert!(opts.single_branch);
+    }
+
+    #[test]
+    fn test_parse_config_submodules() {
+        let s = r#"[repositories]
+a = "foo/a"
+b = { repo = "foo/b", submodules = true }
+c = { repo = "foo/c", submodules = "recursive" }
+"#;
+        let cfg = parse_config(s).unwrap();
+
+        assert_eq!(cfg.options("a").submodules, Submodules::Off);
+        assert_eq!(cfg.options("b").submodules, Submodules::On);
+        assert_eq!(cfg.options("c").submodules, Submodules::Recursive);
+
+        let result = parse_config(r#"repositories.foo = { repo = "foo/foo", submodules = "all" }"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_config_hooks() {
+        let s = r#"post-update = ["make"]
+[repositories]
+a = "foo/a"
+b = { repo = "foo/b", post-clone = ["./configure", "make"], post-update = [] }
+"#;
+        let cfg = parse_config(s).unwrap();
+
+        assert!(cfg.options("a").post_clone.is_empty());
+        assert_eq!(cfg.options("a").post_update, vec!["make".to_string()]);
+        assert_eq!(
+            cfg.options("b").post_clone,
+            vec!["./configure".to_string(), "make".to_string()]
+        );
+        assert!(cfg.options("b").post_update.is_empty());
+    }
+
+    #[test]
+    fn test_parse_config_log() {
+        let cfg = parse_config("[repositories]").unwrap();
+        assert_eq!(cfg.log(), Log::Off);
+
+        let cfg = parse_config("log = true\n[repositories]").unwrap();
+        assert_eq!(cfg.log(), Log::All);
+
+        let cfg = parse_config("log = 10\n[repositories]").unwrap();
+        assert_eq!(cfg.log(), Log::Limit(10));
+
+        let result = parse_config("log = 0\n[repositories]");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_config_git_url() {
+        let s = r#"[repositories]
+a = { type = "git", repo = "https://git.example.com/a.git" }
+b = { url = "git@git.example.com:foo/b.git" }
+c = { type = "git", url = "file:///srv/git/c.git" }
+d = { url = "../local/d" }
+"#;
+        let cfg = parse_config(s).unwrap();
+
+        assert_eq!(
+            cfg.repos.get("a").unwrap().url(),
+            "https://git.example.com/a.git"
+        );
+        assert_eq!(
+            cfg.repos.get("b").unwrap().url(),
+            "git@git.example.com:foo/b.git"
+        );
+        assert_eq!(cfg.repos.get("c").unwrap().url(), "file:///srv/git/c.git");
+        assert_eq!(cfg.repos.get("d").unwrap().url(), "../local/d");
+    }
+
+    #[test]
+    fn test_parse_config_invalid_url() {
+        let result = parse_config(r#"repositories.foo = { url = "" }"#);
+        assert_eq!(format!("{}", result.err().unwrap()), "invalid repo url: ");
+
+        let result = parse_config(r#"repositories.foo = { type = "gitlab", url = "x" }"#);
+        assert_eq!(
+            format!("{}", result.err().unwrap()),
+            "url is not supported for repo type: gitlab"
+        );
+
+        let result = parse_config(r#"repositories.foo = { type = "github" }"#);
+        assert_eq!(
+            format!("{}", result.err().unwrap()),
+            "either repo or url is required"
+        );
+    }
+
+    #[test]
+    fn test_find_config() {
+        let root = std::env::temp_dir().join(format!("gitcop-test-{}", std::process::id()));
+        let sub = root.join("foo").join("bar");
+        fs::create_dir_all(&sub).unwrap();
+        assert_ne!(find_config(&sub), Some(root.join(".gitcop.toml")));
+
+        fs::write(
+            root.join(".gitcop.toml"),
+            "directory = \"repos\"\n[repositories]",
+        )
+        .unwrap();
+        assert_eq!(find_config(&sub), Some(root.join(".gitcop.toml")));
+        assert_eq!(find_config(&root), Some(root.join(".gitcop.toml")));
+
+        let cfg = load_config(find_config(&sub).unwrap()).unwrap();
+        assert_eq!(cfg.dir(), Some(&root.join("repos")));
+        assert_eq!(cfg.lock_file(), Some(root.join(".gitcop.lock")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
     #[test]
     fn test_config_repos_iter_none() {
-        let cfg = Config {
-            git: GitCmd::default(),
-            dir: None,
-            concur: Concurrency::default(),
-            repos: IndexMap::new(),
-        };
-        let mut iter = cfg.repos(None);
+        let cfg = Config::default();
+        let filter = Filter::default();
+        let mut iter = cfg.repos(&filter);
         assert_eq!(iter.next(), None);
     }
 
@@ -333,12 +841,11 @@ concurrency = 123
         let mut repos = IndexMap::new();
         repos.insert("one".to_string(), select.clone());
         let cfg = Config {
-            git: GitCmd::default(),
-            dir: None,
-            concur: Concurrency::default(),
             repos,
+            ..Config::default()
         };
-        let mut iter = cfg.repos(None);
+        let filter = Filter::default();
+        let mut iter = cfg.repos(&filter);
         assert_eq!(iter.next(), Some(Ok(("one", select.as_ref()))));
         assert_eq!(iter.next(), None);
     }
@@ -353,12 +860,11 @@ concurrency = 123
         repos.insert("two".to_string(), select2.clone());
         repos.insert("three".to_string(), select3.clone());
         let cfg = Config {
-            git: GitCmd::default(),
-            dir: None,
-            concur: Concurrency::default(),
             repos,
+            ..Config::default()
         };
-        let mut iter = cfg.repos(None);
+        let filter = Filter::default();
+        let mut iter = cfg.repos(&filter);
         assert_eq!(iter.next(), Some(Ok(("one", select1.as_ref()))));
         assert_eq!(iter.next(), Some(Ok(("two", select2.as_ref()))));
         assert_eq!(iter.next(), Some(Ok(("three", select3.as_ref()))));
@@ -367,14 +873,12 @@ concurrency = 123
 
     #[test]
     fn test_config_repos_iter_none_selected() {
-        let cfg = Config {
-            git: GitCmd::default(),
-            dir: None,
-            concur: Concurrency::default(),
-            repos: IndexMap::new(),
+        let cfg = Config::default();
+        let filter = Filter {
+            names: Some(vec!["one"]),
+            ..Filter::default()
         };
-        let names = vec!["one"];
-        let mut iter = cfg.repos(Some(&names));
+        let mut iter = cfg.repos(&filter);
         assert_eq!(
             iter.next(),
             Some(Err(RepoNotFound {
@@ -394,14 +898,15 @@ concurrency = 123
         repos.insert("two".to_string(), select2.clone());
         repos.insert("three".to_string(), select3.clone());
         let cfg = Config {
-            git: GitCmd::default(),
-            dir: None,
-            concur: Concurrency::default(),
             repos,
+            ..Config::default()
         };
 
-        let names = vec!["one", "three"];
-        let mut iter = cfg.repos(Some(&names));
+        let filter = Filter {
+            names: Some(vec!["one", "three"]),
+            ..Filter::default()
+        };
+        let mut iter = cfg.repos(&filter);
         assert_eq!(iter.next(), Some(Ok(("one", select1.as_ref()))));
         assert_eq!(iter.next(), Some(Ok(("three", select3.as_ref()))));
         assert_eq!(iter.next(), None);
@@ -417,13 +922,12 @@ concurrency = 123
         repos.insert("two".to_string(), select2.clone());
         repos.insert("three".to_string(), select3.clone());
         let cfg = Config {
-            git: GitCmd::default(),
-            dir: None,
-            concur: Concurrency::default(),
             repos,
+            ..Config::default()
         };
 
-        let mut iter = cfg.repos(None);
+        let filter = Filter::default();
+        let mut iter = cfg.repos(&filter);
         assert_eq!(iter.next(), Some(Ok(("one", select1.as_ref()))));
         assert_eq!(iter.next(), Some(Ok(("two", select2.as_ref()))));
         assert_eq!(iter.next(), Some(Ok(("three", select3.as_ref()))));
@@ -440,14 +944,15 @@ concurrency = 123
         repos.insert("two".to_string(), select2.clone());
         repos.insert("three".to_string(), select3.clone());
         let cfg = Config {
-            git: GitCmd::default(),
-            dir: None,
-            concur: Concurrency::default(),
             repos,
+            ..Config::default()
         };
 
-        let names = vec!["two", "three"];
-        let mut iter = cfg.repos(Some(&names));
+        let filter = Filter {
+            names: Some(vec!["two", "three"]),
+            ..Filter::default()
+        };
+        let mut iter = cfg.repos(&filter);
         // changed to RepoKind::Selected
         assert_eq!(iter.next(), Some(Ok(("two", gh!("foo2", "bar2").as_ref()))));
         assert_eq!(
@@ -456,4 +961,120 @@ concurrency = 123
         );
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn test_config_repos_iter_tags() {
+        let s = r#"[repositories]
+lsp-mode = { type = "github", repo = "emacs-lsp", tags = ["lsp"] }
+lsp-ui = { type = "github", repo = "emacs-lsp", tags = ["lsp", "heavy"] }
+org = "bzg"
+[optional-repositories]
+org-roam = "org-roam"
+[groups]
+org = ["org", "org-roam"]
+heavy = ["org-roam"]
+"#;
+        let cfg = parse_config(s).unwrap();
+        assert_eq!(cfg.options("lsp-ui").tags, vec!["lsp", "heavy"]);
+        assert_eq!(cfg.options("org-roam").tags, vec!["org", "heavy"]);
+
+        let names = |filter: &Filter| {
+            cfg.repos(filter)
+                .flatten()
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        let filter = Filter {
+            tags: vec!["lsp"],
+            ..Filter::default()
+        };
+        assert_eq!(names(&filter), vec!["lsp-mode", "lsp-ui"]);
+
+        let filter = Filter {
+            tags: vec!["lsp", "org"],
+            exclude_tags: vec!["heavy"],
+            ..Filter::default()
+        };
+        assert_eq!(names(&filter), vec!["lsp-mode", "org"]);
+
+        let filter = Filter {
+            names: Some(vec!["lsp-ui", "org-roam", "org"]),
+            exclude_tags: vec!["heavy"],
+            ..Filter::default()
+        };
+        assert_eq!(names(&filter), vec!["org"]);
+    }
+
+    #[test]
+    fn test_parse_config_unknown_group_repo() {
+        let s = "[repositories]\n[groups]\nlsp = [\"lsp-mode\"]\n";
+        assert_eq!(
+            format!("{}", parse_config(s).err().unwrap()),
+            "unknown repo in group lsp: lsp-mode"
+        );
+    }
+
+    #[test]
+    fn test_config_repos_iter_patterns() {
+        let mut repos = IndexMap::new();
+        for name in [
+            "magit",
+            "magit-popup",
+            "org",
+            "org-roam",
+            "lsp-mode",
+            "lsp-ui",
+        ] {
+            repos.insert(name.to_string(), gh!("foo", name, o));
+        }
+        let cfg = Config {
+            repos,
+            ..Config::default()
+        };
+        let names = |filter: &Filter| {
+            cfg.repos(filter)
+                .map(|result| result.map(|(name, _)| name.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let filter = Filter {
+            names: Some(vec!["magit*", "org-*", "org-roam", "x*"]),
+            ..Filter::default()
+        };
+        assert_eq!(
+            names(&filter),
+            vec![
+                Ok("magit".to_string()),
+                Ok("magit-popup".to_string()),
+                Ok("org-roam".to_string()),
+                Err(RepoNotFound {
+                    name: "x*".to_string()
+                }),
+            ]
+        );
+
+        let filter = Filter {
+            regexes: vec![Regex::new("^lsp-").unwrap(), Regex::new("^x").unwrap()],
+            ..Filter::default()
+        };
+        assert_eq!(
+            names(&filter),
+            vec![
+                Ok("lsp-mode".to_string()),
+                Ok("lsp-ui".to_string()),
+                Err(RepoNotFound {
+                    name: "^x".to_string()
+                }),
+            ]
+        );
+
+        let filter = Filter {
+            names: Some(vec!["org*"]),
+            ..Filter::default()
+        };
+        assert_eq!(
+            cfg.repos(&filter).next(),
+            Some(Ok(("org", gh!("foo", "org").as_ref())))
+        );
+    }
 }
@@ -0,0 +1,231 @@
+use anyhow::{Error, anyhow};
+use toml_edit::{DocumentMut, InlineTable, Item, Value};
+
+use super::internal::ConfigError;
+use super::{RepoNotFound, parse_config};
+
+fn table_key(optional: bool) -> &'static str {
+    if optional {
+        "optional-repositories"
+    } else {
+        "repositories"
+    }
+}
+
+pub fn add_repo(
+    s: &str,
+    name: &str,
+    spec: &str,
+    type_: Option<&str>,
+    branch: Option<&str>,
+    optional: bool,
+) -> Result<String, Error> {
+    if parse_config(s)?.is_known(name) {
+        return Err(ConfigError::DuplicateRepo {
+            name: name.to_string(),
+        }
+        .into());
+    }
+    let value = match (type_, branch) {
+        (None, None) => Value::from(spec),
+        _ => {
+            let mut table = InlineTable::new();
+            match type_ {
+                Some("git") => {
+                    table.insert("url", spec.into());
+                }
+                _ => {
+                    table.insert("type", type_.unwrap_or("github").into());
+                    table.insert("repo", spec.into());
+                }
+            }
+            if let Some(branch) = branch {
+                table.insert("branch", branch.into());
+            }
+            Value::InlineTable(table)
+        }
+    };
+
+    let mut doc = s.parse::<DocumentMut>()?;
+    let key = table_key(optional);
+    doc.entry(key)
+        .or_insert(toml_edit::table())
+        .as_table_like_mut()
+        .ok_or_else(|| anyhow!("{} is not a table", key))?
+        .insert(name, Item::Value(value));
+    let s = doc.to_string();
+    parse_config(&s)?;
+    Ok(s)
+}
+
+pub fn remove_repo(s: &str, name: &str) -> Result<String, Error> {
+    let mut doc = s.parse::<DocumentMut>()?;
+    let removed = [false, true].into_iter().any(|optional| {
+        doc.get_mut(table_key(optional))
+            .and_then(|item| item.as_table_like_mut())
+            .and_then(|table| table.remove(name))
+            .is_some()
+    });
+    if !removed {
+        return Err(RepoNotFound {
+            name: name.to_string(),
+        }
+        .into());
+    }
+    if let Some(groups) = doc
+        .get_mut("groups")
+        .and_then(|item| item.as_table_like_mut())
+    {
+        for (_, names) in groups.iter_mut() {
+            let Some(names) = names.as_array_mut() else {
+                continue;
+            };
+            let first = names.get(0).and_then(|value| value.as_str()) == Some(name);
+            names.retain(|value| value.as_str() != Some(name));
+            if let (true, Some(value)) = (first, names.get_mut(0)) {
+                value.decor_mut().set_prefix("");
+            }
+        }
+    }
+    let s = doc.to_string();
+    parse_config(&s)?;
+    Ok(s)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::edit::*;
+
+    #[test]
+    fn test_add_repo_simple() {
+        let s = r#"# packages
+directory = "repos"
+
+[repositories]
+# core
+dash = "magnars/dash.el"  # list library
+
+[optional-repositories]
+forge = "magit"
+"#;
+        assert_eq!(
+            add_repo(s, "s", "magnars/s.el", None, None, false).unwrap(),
+            r#"# packages
+directory = "repos"
+
+[repositories]
+# core
+dash = "magnars/dash.el"  # list library
+s = "magnars/s.el"
+
+[optional-repositories]
+forge = "magit"
+"#
+        );
+        assert_eq!(
+            add_repo(s, "magit", "magit", None, None, true).unwrap(),
+            r#"# packages
+directory = "repos"
+
+[repositories]
+# core
+dash = "magnars/dash.el"  # list library
+
+[optional-repositories]
+forge = "magit"
+magit = "magit"
+"#
+        );
+    }
+
+    #[test]
+    fn test_add_repo_normal() {
+        let s = "[repositories]\n";
+        assert_eq!(
+            add_repo(s, "a", "foo/a", Some("gitlab"), Some("dev"), false).unwrap(),
+            "[repositories]\na = { type = \"gitlab\", repo = \"foo/a\", branch = \"dev\" }\n"
+        );
+        assert_eq!(
+            add_repo(s, "b", "https://example.com/b.git", Some("git"), None, true).unwrap(),
+            "[repositories]\n\n[optional-repositories]\nb = { url = \"https://example.com/b.git\" }\n"
+        );
+    }
+
+    #[test]
+    fn test_add_repo_invalid() {
+        let s = "[repositories]\ndash = \"magnars/dash.el\"\n";
+        assert_eq!(
+            format!(
+                "{}",
+                add_repo(s, "dash", "foo", None, None, true).unwrap_err()
+            ),
+            "repo already exists: dash"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                add_repo(s, "x", "a/b/c", None, None, false).unwrap_err()
+            ),
+            "invalid repo name: a/b/c"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                add_repo(s, "x", "a", Some("svn"), None, false).unwrap_err()
+            ),
+            "unknown repo type: svn"
+        );
+    }
+
+    #[test]
+    fn test_remove_repo() {
+        let s = r#"[repositories]
+dash = "magnars/dash.el"  # list library
+s = "magnars/s.el"
+
+[optional-repositories]
+forge = "magit"
+"#;
+        assert_eq!(
+            remove_repo(s, "dash").unwrap(),
+            r#"[repositories]
+s = "magnars/s.el"
+
+[optional-repositories]
+forge = "magit"
+"#
+        );
+        assert_eq!(
+            remove_repo(s, "forge").unwrap(),
+            r#"[repositories]
+dash = "magnars/dash.el"  # list library
+s = "magnars/s.el"
+
+[optional-repositories]
+"#
+        );
+        assert!(remove_repo(s, "magit").is_err());
+    }
+
+    #[test]
+    fn test_remove_repo_in_group() {
+        let s = r#"[repositories]
+a = "foo/a"
+b = "foo/b"
+
+[groups]
+g = ["a", "b"]
+h = ["a"]
+"#;
+        assert_eq!(
+            remove_repo(s, "a").unwrap(),
+            r#"[repositories]
+b = "foo/b"
+
+[groups]
+g = ["b"]
+h = []
+"#
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use std::convert::TryFrom;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -8,7 +10,10 @@ use serde::{Deserialize, Deserializer};
 
 use indexmap::IndexMap;
 
-use super::types::{GitCmd, GitHub, Repo};
+use super::types::{
+    Bitbucket, Codeberg, GitCmd, GitHub, GitLab, GitUrl, Log, Protocol, Repo, RepoOptions, Retry,
+    Revision, SourceHut, Strategy, Submodules,
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigError {
@@ -16,6 +21,20 @@ pub enum ConfigError {
     InvalidRepo { name: String },
     #[error("unknown repo type: {type_:}")]
     UnknownType { type_: String },
+    #[error("invalid repo url: {url:}")]
+    InvalidUrl { url: String },
+    #[error("url is not supported for repo type: {type_:}")]
+    UrlNotSupported { type_: String },
+    #[error("either repo or url is required")]
+    MissingRepo,
+    #[error("only one of branch, tag or rev can be specified")]
+    ConflictingRevision,
+    #[error("repo already exists: {name:}")]
+    DuplicateRepo { name: String },
+    #[error("unknown repo in group {group:}: {name:}")]
+    UnknownGroupRepo { group: String, name: String },
+    #[error("unknown update strategy: {name:}")]
+    UnknownStrategy { name: String },
 }
 
 #[derive(Debug)]
@@ -33,15 +52,39 @@ impl Default for Concurrency {
     }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
+#[derive(Debug)]
 pub enum RepoSpec {
     Simple(String),
-    Normal {
-        #[serde(rename = "type")]
-        type_: String,
-        repo: String,
-    },
+    Normal(Box<RepoTable>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepoTable {
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    pub repo: Option<String>,
+    pub url: Option<String>,
+    pub protocol: Option<Protocol>,
+    pub branch: Option<String>,
+    pub tag: Option<String>,
+    pub rev: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub retries: Option<u32>,
+    #[serde(rename = "retry-delay")]
+    pub retry_delay: Option<u64>,
+    pub timeout: Option<u64>,
+    pub update: Option<Strategy>,
+    pub autostash: Option<bool>,
+    pub depth: Option<u32>,
+    pub filter: Option<String>,
+    #[serde(rename = "single-branch")]
+    pub single_branch: Option<bool>,
+    pub submodules: Option<Submodules>,
+    #[serde(rename = "post-clone")]
+    pub post_clone: Option<Vec<String>>,
+    #[serde(rename = "post-update")]
+    pub post_update: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -51,40 +94,180 @@ pub struct ConfigInternal {
     pub directory: Option<String>,
     #[serde(default)]
     pub concurrency: Concurrency,
+    #[serde(default)]
+    pub protocol: Protocol,
+    #[serde(default)]
+    pub log: Log,
+    pub retries: Option<u32>,
+    #[serde(rename = "retry-delay")]
+    pub retry_delay: Option<u64>,
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub update: Strategy,
+    #[serde(default)]
+    pub autostash: bool,
+    pub depth: Option<u32>,
+    pub filter: Option<String>,
+    #[serde(default, rename = "single-branch")]
+    pub single_branch: bool,
+    #[serde(default)]
+    pub submodules: Submodules,
+    #[serde(default, rename = "post-clone")]
+    pub post_clone: Vec<String>,
+    #[serde(default, rename = "post-update")]
+    pub post_update: Vec<String>,
     pub repositories: IndexMap<String, RepoSpec>,
     #[serde(rename = "optional-repositories")]
     pub optional_repositories: Option<IndexMap<String, RepoSpec>>,
+    #[serde(default)]
+    pub groups: IndexMap<String, Vec<String>>,
 }
 
-impl TryFrom<(&str, &RepoSpec)> for Repo {
+impl TryFrom<(&str, &RepoSpec, Protocol)> for Repo {
     type Error = ConfigError;
 
-    fn try_from((key, val): (&str, &RepoSpec)) -> Result<Self, Self::Error> {
+    fn try_from((key, val, protocol): (&str, &RepoSpec, Protocol)) -> Result<Self, Self::Error> {
         lazy_static! {
             static ref RE: Regex = Regex::new(r"^([^/]+)(?:/([^/]+))?$").unwrap();
         }
-        let spec = match val {
-            RepoSpec::Simple(s) => s,
-            RepoSpec::Normal { type_, repo } => {
-                if type_ == "github" {
-                    repo
-                } else {
-                    return Err(ConfigError::UnknownType {
-                        type_: type_.to_string(),
+        let (type_, spec) = match val {
+            RepoSpec::Simple(s) => ("github", s),
+            RepoSpec::Normal(table) => match (table.type_.as_deref(), &table.repo, &table.url) {
+                (None | Some("git"), _, Some(url)) => ("git", url),
+                (Some(t), _, Some(_)) => {
+                    return Err(ConfigError::UrlNotSupported {
+                        type_: t.to_string(),
                     });
                 }
-            }
+                (t, Some(repo), None) => (t.unwrap_or("github"), repo),
+                (_, None, None) => return Err(ConfigError::MissingRepo),
+            },
+        };
+        let protocol = match val {
+            RepoSpec::Normal(table) => table.protocol.unwrap_or(protocol),
+            RepoSpec::Simple(_) => protocol,
         };
-        if let Some(cap) = RE.captures(spec) {
-            Ok(Repo::GitHub(GitHub::new(
-                cap.get(1).unwrap().as_str(),
-                cap.get(2).map(|m| m.as_str()).unwrap_or(key),
-            )))
-        } else {
-            Err(ConfigError::InvalidRepo {
+        if type_ == "git" {
+            return if spec.trim().is_empty() {
+                Err(ConfigError::InvalidUrl {
+                    url: spec.to_string(),
+                })
+            } else {
+                Ok(Repo::Git(GitUrl::new(spec.as_str())))
+            };
+        }
+        let Some(cap) = RE.captures(spec) else {
+            return Err(ConfigError::InvalidRepo {
                 name: spec.to_string(),
-            })
+            });
+        };
+        let user = cap.get(1).unwrap().as_str();
+        let project = cap.get(2).map(|m| m.as_str()).unwrap_or(key);
+        Ok(match type_ {
+            "github" => Repo::GitHub(GitHub::new(user, project).with_protocol(protocol)),
+            "gitlab" => Repo::GitLab(GitLab::new(user, project).with_protocol(protocol)),
+            "bitbucket" => Repo::Bitbucket(Bitbucket::new(user, project).with_protocol(protocol)),
+            "codeberg" => Repo::Codeberg(Codeberg::new(user, project).with_protocol(protocol)),
+            "sourcehut" => Repo::SourceHut(SourceHut::new(user, project).with_protocol(protocol)),
+            _ => {
+                return Err(ConfigError::UnknownType {
+                    type_: type_.to_string(),
+                });
+            }
+        })
+    }
+}
+
+impl TryFrom<(&RepoSpec, &RepoOptions)> for RepoOptions {
+    type Error = ConfigError;
+
+    fn try_from((val, defaults): (&RepoSpec, &RepoOptions)) -> Result<Self, Self::Error> {
+        let RepoSpec::Normal(table) = val else {
+            return Ok(defaults.clone());
+        };
+        let RepoTable {
+            branch,
+            tag,
+            rev,
+            tags,
+            retries,
+            retry_delay,
+            timeout,
+            update,
+            autostash,
+            depth,
+            filter,
+            single_branch,
+            submodules,
+            post_clone,
+            post_update,
+            ..
+        } = table.as_ref();
+        let rev = match (branch, tag, rev) {
+            (None, None, None) => defaults.rev.clone(),
+            (Some(branch), None, None) => Some(Revision::Branch(branch.to_string())),
+            (None, Some(tag), None) => Some(Revision::Tag(tag.to_string())),
+            (None, None, Some(rev)) => Some(Revision::Rev(rev.to_string())),
+            _ => return Err(ConfigError::ConflictingRevision),
+        };
+        let tags = tags.clone().unwrap_or_else(|| defaults.tags.clone());
+        let retry = Retry {
+            count: retries.unwrap_or(defaults.retry.count),
+            delay: retry_delay.map_or(defaults.retry.delay, Duration::from_secs),
+        };
+        let timeout = timeout.map(Duration::from_secs).or(defaults.timeout);
+        Ok(RepoOptions {
+            rev,
+            tags,
+            retry,
+            timeout,
+            strategy: update.unwrap_or(defaults.strategy),
+            autostash: autostash.unwrap_or(defaults.autostash),
+            depth: depth.or(defaults.depth),
+            filter: filter.clone().or_else(|| defaults.filter.clone()),
+            single_branch: single_branch.unwrap_or(defaults.single_branch),
+            submodules: submodules.unwrap_or(defaults.submodules),
+            post_clone: post_clone
+                .clone()
+                .unwrap_or_else(|| defaults.post_clone.clone()),
+            post_update: post_update
+                .clone()
+                .unwrap_or_else(|| defaults.post_update.clone()),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for RepoSpec {
+    fn deserialize<D>(d: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct RepoSpecVisitor;
+
+        impl<'de> de::Visitor<'de> for RepoSpecVisitor {
+            type Value = RepoSpec;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("repo string or table")
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(RepoSpec::Simple(s.to_string()))
+            }
+
+            fn visit_map<M>(self, map: M) -> Result<Self::Value, M::Error>
+            where
+                M: de::MapAccess<'de>,
+            {
+                let table = RepoTable::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(RepoSpec::Normal(Box::new(table)))
+            }
         }
+
+        d.deserialize_any(RepoSpecVisitor)
     }
 }
 
@@ -113,3 +296,91 @@ impl<'de> Deserialize<'de> for Concurrency {
         Ok(Concurrency(value))
     }
 }
+
+impl<'de> Deserialize<'de> for Protocol {
+    fn deserialize<D>(d: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(d)?;
+        match value.as_str() {
+            "https" => Ok(Protocol::Https),
+            "ssh" => Ok(Protocol::Ssh),
+            _ => Err(de::Error::unknown_variant(&value, &["https", "ssh"])),
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ff-only" => Ok(Strategy::FfOnly),
+            "rebase" => Ok(Strategy::Rebase),
+            "merge" => Ok(Strategy::Merge),
+            "reset" => Ok(Strategy::Reset),
+            _ => Err(ConfigError::UnknownStrategy {
+                name: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Strategy {
+    fn deserialize<D>(d: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(d)?;
+        value.parse().map_err(|_| {
+            de::Error::unknown_variant(&value, &["ff-only", "rebase", "merge", "reset"])
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Log {
+    fn deserialize<D>(d: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Bool(bool),
+            Limit(usize),
+        }
+        match Value::deserialize(d)? {
+            Value::Bool(false) => Ok(Log::Off),
+            Value::Bool(true) => Ok(Log::All),
+            Value::Limit(0) => Err(de::Error::invalid_value(
+                de::Unexpected::Unsigned(0),
+                &"boolean or positive integer",
+            )),
+            Value::Limit(n) => Ok(Log::Limit(n)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Submodules {
+    fn deserialize<D>(d: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Bool(bool),
+            String(String),
+        }
+        match Value::deserialize(d)? {
+            Value::Bool(false) => Ok(Submodules::Off),
+            Value::Bool(true) => Ok(Submodules::On),
+            Value::String(s) if s == "recursive" => Ok(Submodules::Recursive),
+            Value::String(s) => Err(de::Error::invalid_value(
+                de::Unexpected::Str(&s),
+                &"boolean or \"recursive\"",
+            )),
+        }
+    }
+}
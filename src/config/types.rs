@@ -1,51 +1,174 @@
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Protocol {
+    #[default]
+    Https,
+    Ssh,
+}
+
+macro_rules! hosted_repo {
+    ($name:ident, $host:expr, $user_prefix:expr, $suffix:expr) => {
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct $name {
+            pub user: String,
+            pub project: String,
+            pub protocol: Protocol,
+        }
+
+        impl $name {
+            pub fn new<S>(user: S, project: S) -> Self
+            where
+                S: Into<String>,
+            {
+                $name {
+                    user: user.into(),
+                    project: project.into(),
+                    protocol: Protocol::default(),
+                }
+            }
+
+            pub fn with_protocol(self, protocol: Protocol) -> Self {
+                $name { protocol, ..self }
+            }
+        }
+
+        impl Remote for $name {
+            fn url(&self) -> String {
+                let mut url = String::from(match self.protocol {
+                    Protocol::Https => concat!("https://", $host, "/", $user_prefix),
+                    Protocol::Ssh => concat!("git@", $host, ":", $user_prefix),
+                });
+                url.push_str(&self.user);
+                url.push('/');
+                url.push_str(&self.project);
+                url.push_str($suffix);
+                url
+            }
+        }
+    };
+}
+
+hosted_repo!(GitHub, "github.com", "", ".git");
+hosted_repo!(GitLab, "gitlab.com", "", ".git");
+hosted_repo!(Bitbucket, "bitbucket.org", "", ".git");
+hosted_repo!(Codeberg, "codeberg.org", "", ".git");
+hosted_repo!(SourceHut, "git.sr.ht", "~", "");
 
 #[derive(Clone, Debug, PartialEq)]
-pub struct GitHub {
-    pub user: String,
-    pub project: String,
+pub struct GitUrl {
+    pub url: String,
 }
 
-impl GitHub {
-    pub fn new<S>(user: S, project: S) -> Self
+impl GitUrl {
+    pub fn new<S>(url: S) -> Self
     where
         S: Into<String>,
     {
-        GitHub {
-            user: user.into(),
-            project: project.into(),
-        }
+        GitUrl { url: url.into() }
+    }
+}
+
+impl Remote for GitUrl {
+    fn url(&self) -> String {
+        self.url.clone()
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Repo {
     GitHub(GitHub),
+    GitLab(GitLab),
+    Bitbucket(Bitbucket),
+    Codeberg(Codeberg),
+    SourceHut(SourceHut),
+    Git(GitUrl),
 }
 
 pub trait Remote: std::fmt::Debug {
     fn url(&self) -> String;
 }
 
-impl Remote for GitHub {
-    fn url(&self) -> String {
-        let mut url = String::from("https://github.com/");
-        url.push_str(&self.user);
-        url.push('/');
-        url.push_str(&self.project);
-        url.push_str(".git");
-        url
-    }
-}
-
 impl Remote for Repo {
     fn url(&self) -> String {
         match self {
             Repo::GitHub(repo) => repo.url(),
+            Repo::GitLab(repo) => repo.url(),
+            Repo::Bitbucket(repo) => repo.url(),
+            Repo::Codeberg(repo) => repo.url(),
+            Repo::SourceHut(repo) => repo.url(),
+            Repo::Git(repo) => repo.url(),
         }
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum Revision {
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
+/// How a pull updates the working tree.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Strategy {
+    #[default]
+    FfOnly,
+    Rebase,
+    Merge,
+    Reset,
+}
+
+/// Whether to initialize and update submodules after clone and pull.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Submodules {
+    #[default]
+    Off,
+    On,
+    Recursive,
+}
+
+/// Whether to show incoming commits after sync, optionally limited per repo.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Log {
+    #[default]
+    Off,
+    All,
+    Limit(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Retry {
+    pub count: u32,
+    pub delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            count: 0,
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepoOptions {
+    pub rev: Option<Revision>,
+    pub tags: Vec<String>,
+    pub retry: Retry,
+    pub timeout: Option<Duration>,
+    pub strategy: Strategy,
+    pub autostash: bool,
+    pub depth: Option<u32>,
+    pub filter: Option<String>,
+    pub single_branch: bool,
+    pub submodules: Submodules,
+    pub post_clone: Vec<String>,
+    pub post_update: Vec<String>,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Selection<T> {
     Explicit(T),
@@ -1,28 +1,91 @@
 use std::fmt;
-use std::future::Future;
-use std::io;
-use std::path::Path;
-use std::process::Output;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+use std::time::{Duration, Instant};
 
 use anyhow::Error;
 use futures::future::BoxFuture;
+use indicatif::ProgressBar;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::json;
+use tokio::io::AsyncReadExt;
 use tokio::process::Command;
+use tokio::time;
 
-use crate::config::{GitCmd, Remote, Repo};
-use crate::print;
+use crate::config::{GitCmd, Remote, Repo, RepoOptions, Retry, Revision, Strategy, Submodules};
+use crate::print::{self, Format};
+use crate::progress;
 
 pub trait Git<'a> {
-    fn cloner(&'a self, dir: &Path, repo: &Repo) -> AsyncGitResult<'a>;
-    fn pull(&'a self, dir: &Path) -> AsyncGitResult<'a>;
+    fn cloner(
+        &'a self,
+        dir: &Path,
+        repo: &Repo,
+        opts: &RepoOptions,
+        info: RepoInfo,
+    ) -> AsyncGitOutput<'a>;
+    fn pull(&'a self, dir: &Path, opts: &RepoOptions, info: RepoInfo) -> AsyncGitOutput<'a>;
+    fn fetch(&'a self, dir: &Path, opts: &RepoOptions, info: RepoInfo) -> AsyncGitOutput<'a>;
+    fn abort_update(&'a self, dir: &Path, opts: &RepoOptions) -> AsyncGitResult<'a>;
+    fn unshallow(&'a self, dir: &Path, opts: &RepoOptions, info: RepoInfo) -> AsyncGitOutput<'a>;
+    fn is_shallow(&'a self, dir: &Path) -> AsyncGitResult<'a>;
+    fn head(&'a self, dir: &Path) -> AsyncGitResult<'a>;
+    fn remote_refs(&'a self, dir: &Path) -> AsyncGitResult<'a>;
+    fn count_commits(&'a self, dir: &Path, from: &str, to: &str) -> AsyncGitResult<'a>;
+    fn log(&'a self, dir: &Path, from: &str, to: &str, limit: Option<usize>) -> AsyncGitResult<'a>;
+    fn unpushed(&'a self, dir: &Path) -> AsyncGitResult<'a>;
+    fn stash_list(&'a self, dir: &Path) -> AsyncGitResult<'a>;
+    fn stash_push(&'a self, dir: &Path) -> AsyncGitResult<'a>;
+    fn stash_pop(&'a self, dir: &Path) -> AsyncGitResult<'a>;
+    fn status(&'a self, dir: &Path) -> BoxFuture<'a, Result<Status, Error>>;
 }
 
 pub type GitResult = Result<String, Error>;
 pub type AsyncGitResult<'a> = BoxFuture<'a, GitResult>;
+pub type AsyncGitOutput<'a> = BoxFuture<'a, Result<Finished, Error>>;
+
+/// Git operation which finished successfully, possibly after retries.
+#[derive(Debug)]
+pub struct Finished {
+    pub key: String,
+    pub retries: u32,
+}
+
+/// Repo fields included in JSON records of git operations.
+#[derive(Clone, Debug)]
+pub struct RepoInfo {
+    pub url: Option<String>,
+    /// "explicit", "optional" or "unknown" as in list output.
+    pub selection: &'static str,
+    /// Whether the repo directory existed before the operation.
+    pub exists: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Status {
+    pub branch: Option<String>,
+    pub oid: String,
+    pub upstream: Option<String>,
+    pub ahead: u32,
+    pub behind: u32,
+    pub changed: usize,
+    pub untracked: usize,
+    pub origin: Option<String>,
+}
+
+impl Status {
+    pub fn is_clean(&self) -> bool {
+        self.changed == 0 && self.untracked == 0
+    }
+}
 
 #[derive(Debug)]
 pub struct GitError {
     pub key: String,
     pub msg: String,
+    pub retries: u32,
 }
 
 impl std::error::Error for GitError {}
@@ -33,52 +96,776 @@ impl fmt::Display for GitError {
     }
 }
 
-impl<'a> Git<'a> for GitCmd {
-    fn cloner(&'a self, dir: &Path, repo: &Repo) -> AsyncGitResult<'a> {
-        let future = Command::new(self.path())
+#[derive(Debug)]
+pub struct TimeoutError {
+    pub key: String,
+    pub timeout: Duration,
+    pub retries: u32,
+}
+
+impl std::error::Error for TimeoutError {}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: timed out after {}s",
+            self.key,
+            self.timeout.as_secs()
+        )
+    }
+}
+
+impl GitCmd {
+    fn base_command(&self) -> Command {
+        let color = if print::format() == Format::Text {
+            "color.ui=always"
+        } else {
+            "color.ui=never"
+        };
+        let mut cmd = Command::new(self.path());
+        cmd.kill_on_drop(true)
+            .arg("-c")
+            .arg(color)
             .arg("-c")
-            .arg("color.ui=always")
-            .arg("clone")
-            .arg(repo.url())
-            .arg(dir)
-            .output();
+            .arg("advice.detachedHead=false");
+        cmd
+    }
+
+    fn command(&self, dir: &Path) -> Command {
+        let mut cmd = self.base_command();
+        cmd.current_dir(dir);
+        cmd
+    }
+
+    fn update_command(&self, dir: &Path, strategy: Strategy, upstream: &str) -> Command {
+        let mut cmd = self.command(dir);
+        match strategy {
+            Strategy::FfOnly => cmd.arg("merge").arg("--ff-only"),
+            Strategy::Rebase => cmd.arg("rebase"),
+            Strategy::Merge => cmd.arg("merge").arg("--no-edit"),
+            Strategy::Reset => cmd.arg("reset").arg("--hard"),
+        };
+        cmd.arg(upstream);
+        cmd
+    }
+
+    fn submodule_command(
+        &self,
+        dir: &Path,
+        submodules: Submodules,
+        shallow: bool,
+    ) -> Option<Command> {
+        if submodules == Submodules::Off {
+            return None;
+        }
+        let mut cmd = self.command(dir);
+        cmd.arg("submodule").arg("update").arg("--init");
+        if submodules == Submodules::Recursive {
+            cmd.arg("--recursive");
+        }
+        if shallow {
+            cmd.arg("--depth").arg("1");
+        }
+        if progress::enabled() {
+            cmd.arg("--progress");
+        }
+        Some(cmd)
+    }
+
+    /// Returns the command checking out the default branch of origin if HEAD
+    /// is detached.
+    async fn default_branch_checkout(&self, dir: &Path) -> Option<Command> {
         let key = dir.to_string_lossy().into_owned();
-        Box::pin(process_output(key, future))
+        let mut head = self.command(dir);
+        head.arg("symbolic-ref").arg("--quiet").arg("HEAD");
+        if capture_output(key.clone(), head).await.is_ok() {
+            return None;
+        }
+        let mut origin_head = self.command(dir);
+        origin_head
+            .arg("symbolic-ref")
+            .arg("--short")
+            .arg("refs/remotes/origin/HEAD");
+        let origin_head = capture_output(key, origin_head).await.ok()?;
+        let branch = origin_head.strip_prefix("origin/")?;
+        let mut checkout = self.command(dir);
+        checkout.arg("checkout").arg(branch);
+        Some(checkout)
     }
 
-    fn pull(&'a self, dir: &Path) -> AsyncGitResult<'a> {
-        let future = Command::new(self.path())
-            .current_dir(dir)
-            .arg("-c")
-            .arg("color.ui=always")
-            .arg("pull")
-            .arg("--ff-only")
-            .output();
+    /// Returns the command cloning `url` into `dir`. Clone always recurses
+    /// into nested submodules, so only `Recursive` uses it and `On` updates
+    /// submodules by a separate command.
+    fn clone_command(&self, url: &str, dir: &Path, opts: &RepoOptions) -> Command {
+        let mut cmd = self.base_command();
+        cmd.arg("clone");
+        if progress::enabled() {
+            cmd.arg("--progress");
+        }
+        if let Some(depth) = opts.depth {
+            cmd.arg("--depth").arg(depth.to_string());
+        }
+        if let Some(filter) = &opts.filter {
+            cmd.arg(format!("--filter={}", filter));
+        }
+        if opts.single_branch {
+            cmd.arg("--single-branch");
+        }
+        if opts.submodules == Submodules::Recursive {
+            cmd.arg("--recurse-submodules");
+            if opts.depth.is_some() {
+                cmd.arg("--shallow-submodules");
+            }
+        }
+        match &opts.rev {
+            Some(Revision::Branch(name)) | Some(Revision::Tag(name)) => {
+                cmd.arg("--branch").arg(name);
+            }
+            _ => {}
+        }
+        cmd.arg(url).arg(dir);
+        cmd
+    }
+
+    fn fetch_command(&self, dir: &Path) -> Command {
+        let mut cmd = self.command(dir);
+        cmd.arg("fetch");
+        if progress::enabled() {
+            cmd.arg("--progress");
+        }
+        cmd
+    }
+}
+
+impl<'a> Git<'a> for GitCmd {
+    fn cloner(
+        &'a self,
+        dir: &Path,
+        repo: &Repo,
+        opts: &RepoOptions,
+        info: RepoInfo,
+    ) -> AsyncGitOutput<'a> {
+        let staging = staging_dir(dir);
+        let clone = self.clone_command(&repo.url(), &staging, opts);
+        let mut cmds = vec![clone];
+        let checkout_rev = match &opts.rev {
+            Some(Revision::Rev(rev)) => {
+                let mut checkout = self.command(&staging);
+                checkout.arg("checkout").arg("--detach").arg(rev);
+                cmds.push(checkout);
+                true
+            }
+            _ => false,
+        };
+        if checkout_rev || opts.submodules == Submodules::On {
+            cmds.extend(self.submodule_command(&staging, opts.submodules, opts.depth.is_some()));
+        }
+        let key = dir.to_string_lossy().into_owned();
+        let dir = dir.to_path_buf();
+        let (retry, timeout) = (opts.retry, opts.timeout);
+        Box::pin(async move {
+            if staging.exists()
+                && let Err(err) = fs::remove_dir_all(&staging)
+            {
+                return Err(GitError {
+                    key,
+                    msg: format!("Unable to remove {}, {}", staging.display(), err),
+                    retries: 0,
+                }
+                .into());
+            }
+            let result =
+                process_output(key.clone(), "clone", Some(info), cmds, retry, timeout).await;
+            let result = match result {
+                Ok(finished) => match fs::rename(&staging, &dir) {
+                    Ok(()) => Ok(finished),
+                    Err(err) => Err(GitError {
+                        key: key.clone(),
+                        msg: format!("Unable to move clone into place, {}", err),
+                        retries: finished.retries,
+                    }
+                    .into()),
+                },
+                Err(err) => Err(err),
+            };
+            if result.is_err()
+                && staging.exists()
+                && let Err(err) = fs::remove_dir_all(&staging)
+            {
+                print::info(format_args!(
+                    "{}: Unable to remove {}, {}",
+                    print::warn(&key),
+                    staging.display(),
+                    err
+                ));
+            }
+            result
+        })
+    }
+
+    fn pull(&'a self, dir: &Path, opts: &RepoOptions, info: RepoInfo) -> AsyncGitOutput<'a> {
+        let mut cmds = vec![];
+        match &opts.rev {
+            None if opts.strategy == Strategy::Reset => {
+                let fetch = self.fetch_command(dir);
+                let reset = self.update_command(dir, Strategy::Reset, "@{upstream}");
+                cmds.extend([fetch, reset]);
+            }
+            None => {
+                let mut pull = self.command(dir);
+                pull.arg("pull");
+                match opts.strategy {
+                    Strategy::Rebase => pull.arg("--rebase"),
+                    Strategy::Merge => pull.arg("--no-rebase").arg("--no-edit"),
+                    _ => pull.arg("--ff-only"),
+                };
+                if progress::enabled() {
+                    pull.arg("--progress");
+                }
+                cmds.push(pull);
+            }
+            Some(Revision::Branch(branch)) => {
+                let mut fetch = self.fetch_command(dir);
+                fetch.arg("origin");
+                let mut checkout = self.command(dir);
+                checkout.arg("checkout").arg(branch);
+                let update = self.update_command(dir, opts.strategy, &format!("origin/{}", branch));
+                cmds.extend([fetch, checkout, update]);
+            }
+            Some(Revision::Tag(name)) | Some(Revision::Rev(name)) => {
+                let mut fetch = self.fetch_command(dir);
+                fetch.arg("--tags").arg("origin");
+                let mut checkout = self.command(dir);
+                checkout.arg("checkout").arg("--detach").arg(name);
+                cmds.extend([fetch, checkout]);
+            }
+        }
+        cmds.extend(self.submodule_command(dir, opts.submodules, false));
+        let key = dir.to_string_lossy().into_owned();
+        let dir = dir.to_path_buf();
+        let (tracking, retry, timeout) = (opts.rev.is_none(), opts.retry, opts.timeout);
+        Box::pin(async move {
+            // HEAD is left detached by sync --locked, so go back to the
+            // default branch before updating it.
+            if tracking && let Some(checkout) = self.default_branch_checkout(&dir).await {
+                cmds.insert(0, checkout);
+            }
+            process_output(key, "pull", Some(info), cmds, retry, timeout).await
+        })
+    }
+
+    fn fetch(&'a self, dir: &Path, opts: &RepoOptions, info: RepoInfo) -> AsyncGitOutput<'a> {
+        let cmd = self.fetch_command(dir);
+        let key = dir.to_string_lossy().into_owned();
+        Box::pin(process_output(
+            key,
+            "fetch",
+            Some(info),
+            vec![cmd],
+            opts.retry,
+            opts.timeout,
+        ))
+    }
+
+    fn unshallow(&'a self, dir: &Path, opts: &RepoOptions, info: RepoInfo) -> AsyncGitOutput<'a> {
+        let mut cmd = self.fetch_command(dir);
+        cmd.arg("--unshallow");
+        let key = dir.to_string_lossy().into_owned();
+        Box::pin(process_output(
+            key,
+            "unshallow",
+            Some(info),
+            vec![cmd],
+            opts.retry,
+            opts.timeout,
+        ))
+    }
+
+    fn is_shallow(&'a self, dir: &Path) -> AsyncGitResult<'a> {
+        let mut cmd = self.command(dir);
+        cmd.arg("rev-parse").arg("--is-shallow-repository");
+        let key = dir.to_string_lossy().into_owned();
+        Box::pin(capture_output(key, cmd))
+    }
+
+    fn abort_update(&'a self, dir: &Path, opts: &RepoOptions) -> AsyncGitResult<'a> {
+        let mut cmd = self.command(dir);
+        match opts.strategy {
+            Strategy::Rebase => cmd.arg("rebase").arg("--abort"),
+            _ => cmd.arg("merge").arg("--abort"),
+        };
+        let key = dir.to_string_lossy().into_owned();
+        Box::pin(capture_output(key, cmd))
+    }
+
+    fn head(&'a self, dir: &Path) -> AsyncGitResult<'a> {
+        let mut cmd = self.command(dir);
+        cmd.arg("rev-parse").arg("HEAD");
+        let key = dir.to_string_lossy().into_owned();
+        Box::pin(capture_output(key, cmd))
+    }
+
+    fn remote_refs(&'a self, dir: &Path) -> AsyncGitResult<'a> {
+        let mut cmd = self.command(dir);
+        cmd.arg("for-each-ref")
+            .arg("--format=%(objectname) %(refname)")
+            .arg("refs/remotes");
+        let key = dir.to_string_lossy().into_owned();
+        Box::pin(capture_output(key, cmd))
+    }
+
+    fn count_commits(&'a self, dir: &Path, from: &str, to: &str) -> AsyncGitResult<'a> {
+        let mut cmd = self.command(dir);
+        cmd.arg("rev-list")
+            .arg("--count")
+            .arg(format!("{}..{}", from, to));
         let key = dir.to_string_lossy().into_owned();
-        Box::pin(process_output(key, future))
+        Box::pin(capture_output(key, cmd))
     }
+
+    fn log(&'a self, dir: &Path, from: &str, to: &str, limit: Option<usize>) -> AsyncGitResult<'a> {
+        let mut cmd = self.command(dir);
+        cmd.arg("log").arg("--oneline");
+        if let Some(limit) = limit {
+            cmd.arg("-n").arg(limit.to_string());
+        }
+        cmd.arg(format!("{}..{}", from, to));
+        let key = dir.to_string_lossy().into_owned();
+        Box::pin(capture_output(key, cmd))
+    }
+
+    fn unpushed(&'a self, dir: &Path) -> AsyncGitResult<'a> {
+        let mut cmd = self.command(dir);
+        cmd.arg("rev-list")
+            .arg("--count")
+            .arg("HEAD")
+            .arg("--branches")
+            .arg("--not")
+            .arg("--remotes");
+        let key = dir.to_string_lossy().into_owned();
+        Box::pin(capture_output(key, cmd))
+    }
+
+    fn stash_list(&'a self, dir: &Path) -> AsyncGitResult<'a> {
+        let mut cmd = self.command(dir);
+        cmd.arg("stash").arg("list");
+        let key = dir.to_string_lossy().into_owned();
+        Box::pin(capture_output(key, cmd))
+    }
+
+    fn stash_push(&'a self, dir: &Path) -> AsyncGitResult<'a> {
+        let mut cmd = self.command(dir);
+        cmd.arg("stash")
+            .arg("push")
+            .arg("--message")
+            .arg("gitcop autostash");
+        let key = dir.to_string_lossy().into_owned();
+        Box::pin(capture_output(key, cmd))
+    }
+
+    fn stash_pop(&'a self, dir: &Path) -> AsyncGitResult<'a> {
+        let mut cmd = self.command(dir);
+        cmd.arg("stash").arg("pop");
+        let key = dir.to_string_lossy().into_owned();
+        Box::pin(capture_output(key, cmd))
+    }
+
+    fn status(&'a self, dir: &Path) -> BoxFuture<'a, Result<Status, Error>> {
+        let mut status = self.command(dir);
+        status.arg("status").arg("--porcelain=v2").arg("--branch");
+        let mut origin = self.command(dir);
+        origin.arg("config").arg("--get").arg("remote.origin.url");
+        let key = dir.to_string_lossy().into_owned();
+        Box::pin(async move {
+            let mut st = parse_status(&capture_output(key.clone(), status).await?);
+            st.origin = capture_output(key, origin).await.ok();
+            Ok(st)
+        })
+    }
+}
+
+/// Runs hook commands with the shell in the repo directory, stopping at the
+/// first failure.
+pub async fn run_hooks(dir: &Path, op: &str, hooks: &[String]) -> GitResult {
+    let key = dir.to_string_lossy().into_owned();
+    if hooks.is_empty() {
+        return Ok(key);
+    }
+    let cmds = hooks
+        .iter()
+        .map(|hook| {
+            let mut cmd = if cfg!(windows) {
+                let mut cmd = Command::new("cmd");
+                cmd.arg("/C");
+                cmd
+            } else {
+                let mut cmd = Command::new("sh");
+                cmd.arg("-c");
+                cmd
+            };
+            cmd.arg(hook).current_dir(dir).kill_on_drop(true);
+            cmd
+        })
+        .collect();
+    let finished = process_output(key, op, None, cmds, Retry::default(), None).await?;
+    Ok(finished.key)
 }
 
-async fn process_output<F>(key: String, out: F) -> Result<String, Error>
-where
-    F: Future<Output = Result<Output, io::Error>> + Send,
-{
-    let output = out.await?;
-    let success = output.status.success();
-    let colorize = if success { print::good } else { print::warn };
-    print!(
-        "[{}] {}{}",
-        colorize(&key),
-        String::from_utf8(output.stdout)?,
-        String::from_utf8(output.stderr)?
-    );
+/// Returns the hidden directory next to `dir` which a repo is cloned into
+/// before being moved into place.
+pub fn staging_dir(dir: &Path) -> PathBuf {
+    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+    dir.with_file_name(format!(".{}.gitcop-clone", name))
+}
 
+fn parse_status(s: &str) -> Status {
+    let mut st = Status::default();
+    for line in s.lines() {
+        if let Some(header) = line.strip_prefix("# ") {
+            match header.split_once(' ') {
+                Some(("branch.oid", oid)) => st.oid = oid.to_string(),
+                Some(("branch.head", "(detached)")) => st.branch = None,
+                Some(("branch.head", head)) => st.branch = Some(head.to_string()),
+                Some(("branch.upstream", upstream)) => st.upstream = Some(upstream.to_string()),
+                Some(("branch.ab", ab)) => {
+                    for n in ab.split(' ') {
+                        if let Some(ahead) = n.strip_prefix('+') {
+                            st.ahead = ahead.parse().unwrap_or(0);
+                        } else if let Some(behind) = n.strip_prefix('-') {
+                            st.behind = behind.parse().unwrap_or(0);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        } else if line.starts_with("? ") {
+            st.untracked += 1;
+        } else if !line.starts_with("! ") && !line.is_empty() {
+            st.changed += 1;
+        }
+    }
+    st
+}
+
+async fn capture_output(key: String, mut cmd: Command) -> Result<String, Error> {
+    let output = cmd.output().await?;
     if output.status.success() {
-        Ok(key)
+        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    } else {
+        Err(GitError {
+            key,
+            msg: String::from_utf8(output.stderr)?.trim().to_string(),
+            retries: 0,
+        }
+        .into())
+    }
+}
+
+fn is_transient(stderr: &str) -> bool {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r"(?i)could not resolve host|connection reset|connection refused|timed out|failed to connect|early eof|rpc failed|remote end hung up unexpectedly|returned error: 5\d\d"
+        )
+        .unwrap();
+    }
+    RE.is_match(stderr)
+}
+
+async fn run_command(cmd: &mut Command, bar: Option<&ProgressBar>) -> Result<Output, Error> {
+    match bar {
+        Some(bar) => output_with_progress(cmd, bar).await,
+        None => Ok(cmd.output().await?),
+    }
+}
+
+/// Runs the command, showing git's progress output on the bar instead of
+/// capturing it.
+async fn output_with_progress(cmd: &mut Command, bar: &ProgressBar) -> Result<Output, Error> {
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let mut child_stderr = child.stderr.take().unwrap();
+    let read_stderr = async {
+        let mut stderr = vec![];
+        let mut line = vec![];
+        let mut buf = [0; 4096];
+        loop {
+            let n = child_stderr.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            for &b in &buf[..n] {
+                if b != b'\r' && b != b'\n' {
+                    line.push(b);
+                    continue;
+                }
+                if !progress::update(bar, &String::from_utf8_lossy(&line)) {
+                    stderr.extend_from_slice(&line);
+                    stderr.push(b'\n');
+                }
+                line.clear();
+            }
+        }
+        stderr.extend_from_slice(&line);
+        Ok::<_, Error>(stderr)
+    };
+    let (output, stderr) = tokio::join!(child.wait_with_output(), read_stderr);
+    let mut output = output?;
+    output.stderr = stderr?;
+    Ok(output)
+}
+
+async fn process_output(
+    key: String,
+    op: &str,
+    info: Option<RepoInfo>,
+    cmds: Vec<Command>,
+    retry: Retry,
+    timeout: Option<Duration>,
+) -> Result<Finished, Error> {
+    let start = Instant::now();
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    let mut status = None;
+    let mut retries = 0;
+    let bar = progress::repo(&key);
+    let run = async {
+        for mut cmd in cmds {
+            let mut attempt = 0;
+            let output = loop {
+                let output = run_command(&mut cmd, bar.as_ref()).await?;
+                let err = String::from_utf8_lossy(&output.stderr);
+                if output.status.success() || attempt >= retry.count || !is_transient(&err) {
+                    break output;
+                }
+                let delay = retry.delay.saturating_mul(1 << attempt.min(16));
+                attempt += 1;
+                retries += 1;
+                print::info(format_args!(
+                    "[{}] {}, retrying in {:.1}s ({}/{})",
+                    print::warn(&key),
+                    err.trim().lines().last().unwrap_or_default(),
+                    delay.as_secs_f64(),
+                    attempt,
+                    retry.count
+                ));
+                time::sleep(delay).await;
+            };
+            stdout.push_str(&String::from_utf8(output.stdout)?);
+            stderr.push_str(&String::from_utf8(output.stderr)?);
+            if !output.status.success() {
+                status = Some(output.status);
+                break;
+            }
+        }
+        Ok::<_, Error>(())
+    };
+    // The timeout applies to the whole operation, including retries.
+    let timed_out = match timeout {
+        Some(timeout) => match time::timeout(timeout, run).await {
+            Ok(result) => {
+                result?;
+                false
+            }
+            Err(_) => true,
+        },
+        None => {
+            run.await?;
+            false
+        }
+    };
+    let success = status.is_none() && !timed_out;
+    if let Some(bar) = bar {
+        bar.finish_and_clear();
+    }
+    if print::format() == Format::Text {
+        let colorize = if success { print::good } else { print::warn };
+        if timed_out {
+            stderr.push_str("timed out\n");
+        }
+        progress::suspend(|| {
+            if stdout.is_empty() && stderr.is_empty() {
+                println!("[{}]", colorize(&key));
+            } else {
+                print!("[{}] {}{}", colorize(&key), stdout, stderr);
+            }
+        });
     } else {
+        let mut record = json!({
+            "name": key,
+            "operation": op,
+            "success": success,
+            "timed_out": timed_out,
+            "exit_code": if timed_out { None } else { status.map_or(Some(0), |s| s.code()) },
+            "stdout": stdout,
+            "stderr": stderr,
+            "duration": start.elapsed().as_secs_f64(),
+            "retries": retries,
+        });
+        if let Some(info) = info {
+            record["url"] = json!(info.url);
+            record["selection"] = json!(info.selection);
+            record["exists"] = json!(info.exists);
+        }
+        print::record(record);
+    }
+
+    if let (true, Some(timeout)) = (timed_out, timeout) {
+        Err(TimeoutError {
+            key,
+            timeout,
+            retries,
+        }
+        .into())
+    } else if let Some(status) = status {
         Err(GitError {
             key,
-            msg: format!("{}", output.status),
-        }.into())
+            msg: format!("{}", status),
+            retries,
+        }
+        .into())
+    } else {
+        Ok(Finished { key, retries })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git::*;
+
+    #[test]
+    fn test_parse_status() {
+        let s = "# branch.oid 6be5eb90b8d342ba85ef0a1d5c5868a236724a3c
+# branch.head main
+# branch.upstream origin/main
+# branch.ab +1 -2
+1 .M N... 100644 100644 100644 3e2ceb9 3e2ceb9 README.md
+2 R. N... 100644 100644 100644 3e2ceb9 3e2ceb9 R100 b.txt	a.txt
+? new.txt
+! ignored.txt
+";
+        assert_eq!(
+            parse_status(s),
+            Status {
+                branch: Some("main".to_string()),
+                oid: "6be5eb90b8d342ba85ef0a1d5c5868a236724a3c".to_string(),
+                upstream: Some("origin/main".to_string()),
+                ahead: 1,
+                behind: 2,
+                changed: 2,
+                untracked: 1,
+                origin: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_status_detached() {
+        let s = "# branch.oid 6be5eb90b8d342ba85ef0a1d5c5868a236724a3c
+# branch.head (detached)
+";
+        let st = parse_status(s);
+        assert_eq!(st.branch, None);
+        assert_eq!(st.upstream, None);
+        assert!(st.is_clean());
+    }
+
+    fn args(cmd: Command) -> Vec<String> {
+        cmd.as_std()
+            .get_args()
+            .skip(4)
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .filter(|arg| arg != "--progress")
+            .collect()
+    }
+
+    fn clone_args(opts: &RepoOptions) -> Vec<String> {
+        args(GitCmd::default().clone_command("https://example.com/a.git", Path::new("a"), opts))
+    }
+
+    #[test]
+    fn test_submodule_command() {
+        let git = GitCmd::default();
+        let dir = Path::new("a");
+        assert!(git.submodule_command(dir, Submodules::Off, false).is_none());
+        assert_eq!(
+            args(git.submodule_command(dir, Submodules::On, false).unwrap()),
+            vec!["submodule", "update", "--init"]
+        );
+        assert_eq!(
+            args(
+                git.submodule_command(dir, Submodules::Recursive, true)
+                    .unwrap()
+            ),
+            vec![
+                "submodule",
+                "update",
+                "--init",
+                "--recursive",
+                "--depth",
+                "1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clone_command_submodules() {
+        let mut opts = RepoOptions::default();
+        assert_eq!(
+            clone_args(&opts),
+            vec!["clone", "https://example.com/a.git", "a"]
+        );
+        opts.submodules = Submodules::On;
+        assert_eq!(
+            clone_args(&opts),
+            vec!["clone", "https://example.com/a.git", "a"]
+        );
+        opts.submodules = Submodules::Recursive;
+        opts.depth = Some(1);
+        assert_eq!(
+            clone_args(&opts),
+            vec![
+                "clone",
+                "--depth",
+                "1",
+                "--recurse-submodules",
+                "--shallow-submodules",
+                "https://example.com/a.git",
+                "a"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_staging_dir() {
+        assert_eq!(
+            staging_dir(Path::new("magit")),
+            PathBuf::from(".magit.gitcop-clone")
+        );
+        assert_eq!(
+            staging_dir(Path::new("lisp/magit")),
+            PathBuf::from("lisp/.magit.gitcop-clone")
+        );
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(
+            "fatal: unable to access 'https://github.com/foo/bar.git/': Could not resolve host: github.com"
+        ));
+        assert!(is_transient(
+            "fatal: unable to access 'https://github.com/foo/bar.git/': The requested URL returned error: 502"
+        ));
+        assert!(is_transient(
+            "ssh: connect to host github.com port 22: Connection timed out"
+        ));
+        assert!(is_transient(
+            "error: RPC failed; curl 56 Recv failure: Connection reset by peer"
+        ));
+        assert!(!is_transient(
+            "fatal: unable to access 'https://github.com/foo/bar.git/': The requested URL returned error: 403"
+        ));
+        assert!(!is_transient(
+            "fatal: Not possible to fast-forward, aborting."
+        ));
     }
 }
@@ -1,4 +1,6 @@
 pub mod cmd;
 pub mod config;
 mod git;
+mod lock;
 pub mod print;
+mod progress;
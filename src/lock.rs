@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Error;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LockEntry {
+    pub url: String,
+    pub commit: String,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Lock {
+    #[serde(default)]
+    repositories: IndexMap<String, LockEntry>,
+}
+
+impl Lock {
+    pub fn get(&self, name: &str) -> Option<&LockEntry> {
+        self.repositories.get(name)
+    }
+
+    pub fn insert(&mut self, name: &str, entry: LockEntry) {
+        self.repositories.insert(name.to_string(), entry);
+    }
+}
+
+pub fn load_lock<P>(path: P) -> Result<Lock, Error>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(Lock::default());
+    }
+    parse_lock(&fs::read_to_string(path)?)
+}
+
+pub fn parse_lock(s: &str) -> Result<Lock, Error> {
+    Ok(toml::from_str(s)?)
+}
+
+pub fn save_lock<P>(path: P, lock: &Lock) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+{
+    fs::write(path, toml::to_string(lock)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lock::*;
+
+    #[test]
+    fn test_lock_roundtrip() {
+        let mut lock = Lock::default();
+        lock.insert(
+            "dash",
+            LockEntry {
+                url: "https://github.com/magnars/dash.el.git".to_string(),
+                commit: "0123456789abcdef0123456789abcdef01234567".to_string(),
+            },
+        );
+        let s = toml::to_string(&lock).unwrap();
+        assert_eq!(
+            s,
+            r#"[repositories.dash]
+url = "https://github.com/magnars/dash.el.git"
+commit = "0123456789abcdef0123456789abcdef01234567"
+"#
+        );
+        assert_eq!(parse_lock(&s).unwrap(), lock);
+    }
+
+    #[test]
+    fn test_lock_empty() {
+        assert_eq!(parse_lock("").unwrap(), Lock::default());
+    }
+}
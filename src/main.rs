@@ -1,12 +1,118 @@
 use std::env;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::time::Duration;
 
-use clap::{crate_name, crate_version, Arg, ArgAction, Command};
+use clap::{crate_name, crate_version, Arg, ArgAction, ArgMatches, Command};
+use regex::Regex;
 
 use gitcop::cmd;
-use gitcop::config;
+use gitcop::config::{self, Filter, Log};
 use gitcop::print;
 
+fn tag_args(cmd: Command) -> Command {
+    cmd.arg(
+        Arg::new("tag")
+            .long("tag")
+            .value_name("TAG")
+            .action(ArgAction::Append)
+            .help("Select repos with the tag"),
+    )
+    .arg(
+        Arg::new("exclude-tag")
+            .long("exclude-tag")
+            .value_name("TAG")
+            .action(ArgAction::Append)
+            .help("Exclude repos with the tag"),
+    )
+}
+
+fn repo_args(cmd: Command) -> Command {
+    tag_args(cmd)
+        .arg(
+            Arg::new("regex")
+                .long("regex")
+                .value_name("PATTERN")
+                .action(ArgAction::Append)
+                .conflicts_with("tag")
+                .help("Select repos whose name matches the regex"),
+        )
+        .arg(
+            Arg::new("REPO")
+                .action(ArgAction::Append)
+                .num_args(0..)
+                .conflicts_with("tag")
+                .help("Repo name or glob pattern"),
+        )
+}
+
+fn update_args(cmd: Command) -> Command {
+    cmd.arg(
+        Arg::new("strategy")
+            .long("strategy")
+            .value_name("STRATEGY")
+            .value_parser(["ff-only", "rebase", "merge", "reset"])
+            .help("Update strategy, overrides update in config"),
+    )
+    .arg(
+        Arg::new("force")
+            .short('f')
+            .long("force")
+            .action(ArgAction::SetTrue)
+            .help("Reset even if repos have local changes"),
+    )
+    .arg(
+        Arg::new("autostash")
+            .long("autostash")
+            .action(ArgAction::SetTrue)
+            .help("Stash local changes before updating and reapply them afterwards"),
+    )
+}
+
+fn values<'a>(m: &'a ArgMatches, id: &str) -> Option<Vec<&'a str>> {
+    m.try_get_many::<String>(id)
+        .ok()
+        .flatten()
+        .map(|v| v.map(|s| s.as_str()).collect())
+}
+
+fn repo_filter(m: &ArgMatches) -> Filter<'_> {
+    let regexes = values(m, "regex")
+        .unwrap_or_default()
+        .into_iter()
+        .map(|re| {
+            Regex::new(re).unwrap_or_else(|err| {
+                eprintln!("Invalid regex \"{}\", {}", re, err);
+                exit(1)
+            })
+        })
+        .collect();
+    Filter {
+        names: values(m, "REPO"),
+        regexes,
+        tags: values(m, "tag").unwrap_or_default(),
+        exclude_tags: values(m, "exclude-tag").unwrap_or_default(),
+    }
+}
+
+/// Resolves a directory argument against `cwd`, returning it relative to the
+/// repos directory if it is inside, such that it matches the repo name.
+fn resolve_dir(cwd: &Path, base: Option<&PathBuf>, dir: &str) -> String {
+    let path = cwd.join(dir);
+    let path = path
+        .canonicalize()
+        .or_else(|_| std::path::absolute(&path))
+        .unwrap_or(path);
+    base.and_then(|base| base.canonicalize().ok())
+        .and_then(|base| {
+            path.strip_prefix(base)
+                .ok()
+                .filter(|rel| !rel.as_os_str().is_empty())
+                .map(|rel| rel.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| path.to_string_lossy().into_owned())
+}
+
 #[tokio::main]
 async fn main() {
     print::color_init();
@@ -14,8 +120,57 @@ async fn main() {
     let matches = Command::new(crate_name!())
         .version(crate_version!())
         .arg_required_else_help(true)
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .global(true)
+                .value_name("PATH")
+                .help("Path to .gitcop.toml, defaults to $GITCOP_CONFIG or search from current directory"),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .global(true)
+                .value_parser(["text", "json", "ndjson"])
+                .default_value("text")
+                .help("Output format"),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .global(true)
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .help("Kill git operations running longer than SECS, overrides timeout in config"),
+        )
         .subcommands([
-            Command::new("list")
+            Command::new("add")
+                .about("Add repo to .gitcop.toml and clone it")
+                .arg(Arg::new("NAME").required(true))
+                .arg(Arg::new("SPEC").required(true))
+                .arg(
+                    Arg::new("optional")
+                        .short('o')
+                        .long("optional")
+                        .action(ArgAction::SetTrue)
+                        .help("Add to optional repositories"),
+                )
+                .arg(
+                    Arg::new("type")
+                        .short('t')
+                        .long("type")
+                        .value_name("TYPE")
+                        .help("Repo type, such as github, gitlab or git"),
+                )
+                .arg(
+                    Arg::new("branch")
+                        .short('b')
+                        .long("branch")
+                        .value_name("BRANCH")
+                        .help("Branch to check out"),
+                ),
+            tag_args(Command::new("list"))
                 .about("List repos")
                 .arg(
                     Arg::new("default")
@@ -38,36 +193,125 @@ async fn main() {
                         .action(ArgAction::SetTrue)
                         .help("List unknown directories"),
                 ),
-            Command::new("pull").about("Pull in directories").arg(
+            update_args(Command::new("pull")).about("Pull in directories").arg(
                 Arg::new("DIR")
                     .required(true)
                     .action(ArgAction::Append)
                     .num_args(1..),
             ),
-            Command::new("sync")
+            Command::new("remove")
+                .about("Remove repo from .gitcop.toml and delete its directory")
+                .arg(Arg::new("NAME").required(true))
+                .arg(
+                    Arg::new("keep-dir")
+                        .short('k')
+                        .long("keep-dir")
+                        .action(ArgAction::SetTrue)
+                        .help("Keep the repo directory"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("Delete the directory even if it has local changes"),
+                ),
+            update_args(repo_args(Command::new("sync")))
                 .about("Sync repos")
-                .arg(Arg::new("REPO").action(ArgAction::Append).num_args(0..)),
+                .arg(
+                    Arg::new("locked")
+                        .long("locked")
+                        .action(ArgAction::SetTrue)
+                        .help("Check out commits recorded in .gitcop.lock"),
+                )
+                .arg(
+                    Arg::new("log")
+                        .long("log")
+                        .value_name("N")
+                        .num_args(0..=1)
+                        .require_equals(true)
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .help("Show incoming commits of updated repos, at most N per repo"),
+                ),
+            repo_args(Command::new("fetch")).about("Fetch repos without updating working trees"),
+            repo_args(Command::new("unshallow")).about("Fetch full history of shallow repos"),
+            repo_args(Command::new("status")).about("Show working tree status of repos"),
+            repo_args(Command::new("lock"))
+                .about("Show or update .gitcop.lock")
+                .arg(
+                    Arg::new("update")
+                        .short('u')
+                        .long("update")
+                        .action(ArgAction::SetTrue)
+                        .help("Record current commits of repos"),
+                ),
         ])
         .get_matches();
 
-    let cfg = match config::load_config(".gitcop.toml") {
+    let format = match matches.get_one::<String>("format").map(|s| s.as_str()) {
+        Some("json") => print::Format::Json,
+        Some("ndjson") => print::Format::Ndjson,
+        _ => print::Format::Text,
+    };
+    print::set_format(format);
+
+    let path = if let Some(path) = matches.get_one::<String>("config") {
+        PathBuf::from(path)
+    } else if let Some(path) = env::var_os("GITCOP_CONFIG") {
+        PathBuf::from(path)
+    } else {
+        let found = env::current_dir().ok().and_then(config::find_config);
+        match found {
+            Some(path) => path,
+            None => {
+                eprintln!("Unable to find .gitcop.toml in current or parent directories");
+                exit(1)
+            }
+        }
+    };
+    let mut cfg = match config::load_config(&path) {
         Ok(cfg) => cfg,
         Err(err) => {
-            eprintln!("Unable to load .gitcop.toml, {}", err);
+            eprintln!("Unable to load {}, {}", path.display(), err);
             exit(1)
         }
     };
-    if let Some(dir) = cfg.dir() {
-        if let Err(err) = env::set_current_dir(dir) {
-            eprintln!(
-                "Unable to change directory to \"{}\", {}",
-                dir.display(),
-                err
-            );
-            exit(1)
-        }
+    if let Some(timeout) = matches.get_one::<u64>("timeout") {
+        cfg.set_timeout(Duration::from_secs(*timeout));
+    }
+    if let Some((_, sub_m)) = matches.subcommand()
+        && let Ok(Some(strategy)) = sub_m.try_get_one::<String>("strategy")
+    {
+        cfg.set_strategy(strategy.parse().unwrap());
+    }
+    if let Some((_, sub_m)) = matches.subcommand()
+        && let Ok(Some(true)) = sub_m.try_get_one::<bool>("autostash")
+    {
+        cfg.set_autostash();
+    }
+    let cwd = env::current_dir().unwrap_or_default();
+    if let Some(dir) = cfg.dir()
+        && let Err(err) = env::set_current_dir(dir)
+    {
+        eprintln!(
+            "Unable to change directory to \"{}\", {}",
+            dir.display(),
+            err
+        );
+        exit(1)
     }
     match matches.subcommand() {
+        Some(("add", sub_m)) => {
+            cmd::add(
+                &cfg,
+                sub_m.get_one::<String>("NAME").unwrap(),
+                sub_m.get_one::<String>("SPEC").unwrap(),
+                sub_m.get_one::<String>("type").map(|s| s.as_str()),
+                sub_m.get_one::<String>("branch").map(|s| s.as_str()),
+                sub_m.get_flag("optional"),
+            )
+            .await
+        }
         Some(("list", sub_m)) => {
             if sub_m.get_flag("unknown") {
                 cmd::list_unknown(&cfg)
@@ -78,30 +322,69 @@ async fn main() {
                     default = true;
                     optional = true;
                 }
-                cmd::list(&cfg, default, optional)
+                cmd::list(&cfg, &repo_filter(sub_m), default, optional)
             }
         }
         Some(("pull", sub_m)) => {
             if let Some(dirs) = sub_m.get_many::<String>("DIR") {
-                cmd::pull(&cfg, dirs.map(|s| s.as_str())).await
+                let dirs = dirs
+                    .map(|dir| resolve_dir(&cwd, cfg.dir(), dir))
+                    .collect::<Vec<_>>();
+                cmd::pull(
+                    &cfg,
+                    dirs.iter().map(|s| s.as_str()),
+                    sub_m.get_flag("force"),
+                )
+                .await
             } else {
                 Ok(())
             }
         }
+        Some(("remove", sub_m)) => {
+            cmd::remove(
+                &cfg,
+                sub_m.get_one::<String>("NAME").unwrap(),
+                sub_m.get_flag("keep-dir"),
+                sub_m.get_flag("force"),
+            )
+            .await
+        }
         Some(("sync", sub_m)) => {
-            if let Some(names) = sub_m.get_many::<String>("REPO") {
-                cmd::sync(&cfg, Some(&names.map(|s| s.as_str()).collect())).await
-            } else {
-                cmd::sync(&cfg, None).await
-            }
+            let log = match sub_m
+                .contains_id("log")
+                .then(|| sub_m.get_one::<u64>("log"))
+            {
+                Some(Some(n)) => Log::Limit(*n as usize),
+                Some(None) => Log::All,
+                None => cfg.log(),
+            };
+            cmd::sync(
+                &cfg,
+                &repo_filter(sub_m),
+                sub_m.get_flag("locked"),
+                log,
+                sub_m.get_flag("force"),
+            )
+            .await
+        }
+        Some(("fetch", sub_m)) => cmd::fetch(&cfg, &repo_filter(sub_m)).await,
+        Some(("unshallow", sub_m)) => cmd::unshallow(&cfg, &repo_filter(sub_m)).await,
+        Some(("status", sub_m)) => cmd::status(&cfg, &repo_filter(sub_m)).await,
+        Some(("lock", sub_m)) => {
+            cmd::lock(&cfg, &repo_filter(sub_m), sub_m.get_flag("update")).await
         }
         _ => Ok(()),
     }
     .unwrap_or_else(|err| {
+        if err.is::<cmd::Cancelled>() {
+            print::flush();
+            exit(130);
+        }
         eprintln!(
             "gitcop: {} failed, Error: {}",
             matches.subcommand_name().unwrap_or("unknown"),
             err
         );
-    })
+    });
+    print::flush();
 }
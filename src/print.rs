@@ -1,10 +1,63 @@
+use std::fmt::Display;
+use std::sync::{Mutex, OnceLock};
+
+use serde_json::Value;
 use yansi::{Paint, Painted};
 
+use crate::progress;
+
 type Printable<'a> = Painted<&'a str>;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Ndjson,
+}
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+static RECORDS: Mutex<Vec<Value>> = Mutex::new(Vec::new());
+
 pub fn color_init() {
 }
 
+pub fn set_format(format: Format) {
+    if format != Format::Text {
+        yansi::disable();
+    }
+    let _ = FORMAT.set(format);
+}
+
+pub fn format() -> Format {
+    FORMAT.get().copied().unwrap_or(Format::Text)
+}
+
+pub fn record(value: Value) {
+    match format() {
+        Format::Text => {}
+        Format::Json => RECORDS.lock().unwrap().push(value),
+        Format::Ndjson => println!("{}", value),
+    }
+}
+
+pub fn flush() {
+    if format() == Format::Json {
+        let records = std::mem::take(&mut *RECORDS.lock().unwrap());
+        println!("{}", Value::Array(records));
+    }
+}
+
+pub fn info<D>(msg: D)
+where
+    D: Display,
+{
+    if format() == Format::Text {
+        progress::suspend(|| println!("{}", msg));
+    } else {
+        eprintln!("{}", msg);
+    }
+}
+
 pub fn warn(s: &str) -> Printable<'_> {
     s.red()
 }
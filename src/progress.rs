@@ -0,0 +1,118 @@
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::print::{self, Format};
+
+static MULTI: OnceLock<Option<MultiProgress>> = OnceLock::new();
+
+fn multi() -> Option<&'static MultiProgress> {
+    MULTI
+        .get_or_init(|| {
+            if print::format() == Format::Text && io::stdout().is_terminal() {
+                Some(MultiProgress::with_draw_target(ProgressDrawTarget::stdout()))
+            } else {
+                None
+            }
+        })
+        .as_ref()
+}
+
+/// Returns true if progress is displayed, that is, output is text to a TTY.
+pub fn enabled() -> bool {
+    multi().is_some()
+}
+
+/// Runs `f` with progress bars hidden so that its output is not garbled.
+pub fn suspend<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    match multi() {
+        Some(multi) => multi.suspend(f),
+        None => f(),
+    }
+}
+
+pub fn overall(total: usize, name: &str) -> Option<ProgressBar> {
+    let style = ProgressStyle::with_template("{prefix} [{pos}/{len}] {elapsed}").unwrap();
+    let bar = ProgressBar::new(total as u64)
+        .with_style(style)
+        .with_prefix(name.to_string());
+    Some(multi()?.insert(0, bar))
+}
+
+pub fn repo(key: &str) -> Option<ProgressBar> {
+    let style =
+        ProgressStyle::with_template("{spinner} {prefix:<19} [{bar:20}] {pos:>3}% {wide_msg}")
+            .unwrap()
+            .progress_chars("=> ");
+    let bar = ProgressBar::new(100)
+        .with_style(style)
+        .with_prefix(key.to_string());
+    let bar = multi()?.add(bar);
+    bar.enable_steady_tick(Duration::from_millis(100));
+    Some(bar)
+}
+
+/// Updates the bar if the line is git's progress output and returns true,
+/// otherwise returns false.
+pub fn update(bar: &ProgressBar, line: &str) -> bool {
+    match parse_progress(line) {
+        Some((phase, percent)) => {
+            bar.set_message(phase.to_string());
+            bar.set_position(percent.unwrap_or(0));
+            true
+        }
+        None => false,
+    }
+}
+
+fn parse_progress(line: &str) -> Option<(&str, Option<u64>)> {
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r"^(?:remote: )?([A-Z][a-z]+(?: [a-z]+)*):\s+(?:(\d+)% \(|\d+)").unwrap();
+        static ref TOTAL_RE: Regex = Regex::new(r"^(?:remote: )?Total \d+ \(delta").unwrap();
+    }
+    if TOTAL_RE.is_match(line) {
+        return Some(("Total", Some(100)));
+    }
+    let cap = RE.captures(line)?;
+    let phase = cap.get(1).unwrap().as_str();
+    let percent = cap.get(2).and_then(|m| m.as_str().parse().ok());
+    Some((phase, percent))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::progress::*;
+
+    #[test]
+    fn test_parse_progress() {
+        assert_eq!(
+            parse_progress("Receiving objects:  45% (450/1000), 1.20 MiB | 512.00 KiB/s"),
+            Some(("Receiving objects", Some(45)))
+        );
+        assert_eq!(
+            parse_progress("remote: Counting objects: 100% (5/5), done."),
+            Some(("Counting objects", Some(100)))
+        );
+        assert_eq!(
+            parse_progress("remote: Enumerating objects: 5, done."),
+            Some(("Enumerating objects", None))
+        );
+        assert_eq!(
+            parse_progress("remote: Total 3 (delta 0), reused 0 (delta 0), pack-reused 0"),
+            Some(("Total", Some(100)))
+        );
+        assert_eq!(parse_progress("Cloning into 'magit'..."), None);
+        assert_eq!(
+            parse_progress("fatal: Not possible to fast-forward, aborting."),
+            None
+        );
+    }
+}